@@ -18,10 +18,13 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use std::{ptr, slice};
+
 use libc::{
     c_int,
     c_uint,
-    c_uchar
+    c_uchar,
+    c_void
 };
 
 use midi::*;
@@ -38,9 +41,11 @@ const SND_SEQ_ADDRESS_UNKNOWN: c_uchar = 253;
 
 const SND_SEQ_EVENT_LENGTH_MASK: c_uint = 3 << 2;
 const SND_SEQ_EVENT_LENGTH_FIXED: c_uint = 0 << 2;
+const SND_SEQ_EVENT_LENGTH_VARIABLE: c_uint = 1 << 2;
 
 pub trait TransliteratedFromCMacros {
     fn set_fixed(&mut self);
+    fn set_variable(&mut self, data: &mut [u8]);
     fn set_direct(&mut self);
     fn set_subs(&mut self);
     fn set_source(&mut self, port: c_uchar);
@@ -51,6 +56,8 @@ pub trait TransliteratedFromCMacros {
 
     fn set_cc(&mut self, _type: c_uint, channel: Channel, controller_number: u16, position: u8);
     fn set_cc14(&mut self, _type: c_uint, channel: Channel, controller_number: u16, position: u16);
+    fn set_pitchbend(&mut self, channel: Channel, bend: u16);
+    fn set_sysex(&mut self, data: &mut [u8]);
 
 }
 
@@ -61,6 +68,20 @@ impl TransliteratedFromCMacros for snd_seq_event_t {
         self.flags |= SND_SEQ_EVENT_LENGTH_FIXED as u8;
     }
 
+    // alsa packs snd_seq_ev_ext_t, alsa-sys's copy of it isn't, so the length and pointer go
+    // in by hand
+    #[inline]
+    fn set_variable(&mut self, data: &mut [u8]) {
+        self.flags &= !(SND_SEQ_EVENT_LENGTH_MASK as u8);
+        self.flags |= SND_SEQ_EVENT_LENGTH_VARIABLE as u8;
+
+        let ext = self.data.data.as_mut_ptr() as *mut u8;
+        unsafe {
+            ptr::write_unaligned(ext as *mut c_uint, data.len() as c_uint);
+            ptr::write_unaligned(ext.add(4) as *mut *mut c_void, data.as_mut_ptr() as *mut c_void);
+        }
+    }
+
     #[inline]
     fn set_direct(&mut self) {
         self.queue = SND_SEQ_QUEUE_DIRECT;
@@ -85,7 +106,7 @@ impl TransliteratedFromCMacros for snd_seq_event_t {
 
         let note = self.data.note();
         unsafe {
-            (*note).channel = (channel as c_uchar) + 1;
+            (*note).channel = channel as c_uchar;
             (*note).note = note_number;
             (*note).velocity = velocity;
             (*note).duration = duration;
@@ -103,7 +124,7 @@ impl TransliteratedFromCMacros for snd_seq_event_t {
         //self.set_fixed()
         let ctrl = self.data.control();
         unsafe {
-            (*ctrl).channel = (channel as c_uchar) + 1;
+            (*ctrl).channel = channel as c_uchar;
             (*ctrl).param = controller_number as c_uint;
             (*ctrl).value = position as c_int;
         }
@@ -113,19 +134,37 @@ impl TransliteratedFromCMacros for snd_seq_event_t {
         //self.set_fixed()
         let ctrl = self.data.control();
         unsafe {
-            (*ctrl).channel = (channel as c_uchar) + 1;
+            (*ctrl).channel = channel as c_uchar;
             (*ctrl).param = controller_number as c_uint;
             (*ctrl).value = position as c_int;
         }
     }
 
+    // alsa's pitch bend is signed around the center, MIDI's goes 0-16383 with 8192 in the middle
+    fn set_pitchbend(&mut self, channel: Channel, bend: u16) {
+        self._type = SND_SEQ_EVENT_PITCHBEND as snd_seq_event_type_t;
+        let ctrl = self.data.control();
+        unsafe {
+            (*ctrl).channel = channel as c_uchar;
+            (*ctrl).param = 0;
+            (*ctrl).value = bend as c_int - 8192;
+        }
+    }
+
+    // the whole message, F0 to F7
+    fn set_sysex(&mut self, data: &mut [u8]) {
+        self._type = SND_SEQ_EVENT_SYSEX as snd_seq_event_type_t;
+        self.set_variable(data);
+    }
+
 }
 pub trait ToSndSeqEvent {
-    fn to_snd_seq_event(&self) -> Option<snd_seq_event_t>;
+    /// Variable length events like SysEx point into `ext`, it has to outlive the event.
+    fn to_snd_seq_event(&self, ext: &mut Vec<u8>) -> Option<snd_seq_event_t>;
 }
 
 impl ToSndSeqEvent for Message {
-    fn to_snd_seq_event(&self) -> Option<snd_seq_event_t> {
+    fn to_snd_seq_event(&self, ext: &mut Vec<u8>) -> Option<snd_seq_event_t> {
         let mut ev = snd_seq_event_t {
             _type: 0,
             flags: 0,
@@ -161,6 +200,9 @@ impl ToSndSeqEvent for Message {
             Message::PolyphonicPressure(channel, note_number, velocity) =>
                 ev.set_note(SND_SEQ_EVENT_KEYPRESS, channel, note_number, velocity, 0),
 
+            Message::ChannelPressure(channel, pressure) =>
+                ev.set_cc(SND_SEQ_EVENT_CHANPRESS, channel, 0, pressure),
+
             Message::Start =>
                 ev.set_button(SND_SEQ_EVENT_START),
 
@@ -170,15 +212,121 @@ impl ToSndSeqEvent for Message {
             Message::Stop =>
                 ev.set_button(SND_SEQ_EVENT_STOP),
 
+            Message::PitchBend(channel, bend) =>
+                ev.set_pitchbend(channel, bend),
+
+            Message::ControlChange(channel, control_number, pos) =>
+                ev.set_cc(SND_SEQ_EVENT_CONTROLLER, channel, control_number as u16, pos),
+
+            Message::ProgramChange(channel, program) =>
+                ev.set_cc(SND_SEQ_EVENT_PGMCHANGE, channel, 0, program),
+
             Message::RPN7(channel, control_number, pos) =>
                 ev.set_cc(SND_SEQ_EVENT_CONTROLLER, channel, control_number, pos),
 
             Message::RPN14(channel, control_number, pos) =>
                 ev.set_cc14(SND_SEQ_EVENT_CONTROLLER, channel, control_number, pos),
 
+            // alsa always sends the data entry MSB/LSB pair for NRPNs, so a 7-bit value goes in
+            // the MSB
+            Message::NRPN7(channel, nrpn, pos) =>
+                ev.set_cc14(SND_SEQ_EVENT_NONREGPARAM, channel, nrpn, (pos as u16) << 7),
+
+            Message::NRPN14(channel, nrpn, pos) =>
+                ev.set_cc14(SND_SEQ_EVENT_NONREGPARAM, channel, nrpn, pos),
+
+            Message::SysEx(..) => {
+                *ext = self.to_raw_messages().into_iter()
+                    .filter_map(|raw| match raw {
+                        Raw(byte) => Some(byte),
+                        _ => None
+                    })
+                    .collect();
+
+                ev.set_sysex(ext)
+            },
+
             _ => return None
         }
 
         Some(ev)
     }
 }
+
+const CHANNELS: [Channel; 16] = [
+    Ch1, Ch2, Ch3, Ch4, Ch5, Ch6, Ch7, Ch8,
+    Ch9, Ch10, Ch11, Ch12, Ch13, Ch14, Ch15, Ch16
+];
+
+pub trait FromSndSeqEvent {
+    fn to_message(&self) -> Option<Message>;
+}
+
+impl FromSndSeqEvent for snd_seq_event_t {
+    fn to_message(&self) -> Option<Message> {
+        let data = &self.data as *const Union_Unnamed10;
+        let (note, ctrl) = unsafe {
+            (&*(data as *const snd_seq_ev_note_t), &*(data as *const snd_seq_ev_ctrl_t))
+        };
+
+        let note_channel = CHANNELS[(note.channel & 0x0F) as usize];
+        let ctrl_channel = CHANNELS[(ctrl.channel & 0x0F) as usize];
+
+        let msg = match self._type as c_uint {
+            SND_SEQ_EVENT_NOTEON =>
+                Message::NoteOn(note_channel, note.note, note.velocity),
+
+            SND_SEQ_EVENT_NOTEOFF =>
+                Message::NoteOff(note_channel, note.note, note.velocity),
+
+            SND_SEQ_EVENT_KEYPRESS =>
+                Message::PolyphonicPressure(note_channel, note.note, note.velocity),
+
+            SND_SEQ_EVENT_CONTROLLER =>
+                Message::ControlChange(ctrl_channel, ctrl.param as U7, ctrl.value as U7),
+
+            SND_SEQ_EVENT_PGMCHANGE =>
+                Message::ProgramChange(ctrl_channel, ctrl.value as U7),
+
+            SND_SEQ_EVENT_CHANPRESS =>
+                Message::ChannelPressure(ctrl_channel, ctrl.value as U7),
+
+            SND_SEQ_EVENT_PITCHBEND =>
+                Message::PitchBend(ctrl_channel, (ctrl.value + 8192) as U14),
+
+            SND_SEQ_EVENT_START => Message::Start,
+            SND_SEQ_EVENT_CONTINUE => Message::Continue,
+            SND_SEQ_EVENT_STOP => Message::Stop,
+            SND_SEQ_EVENT_CLOCK => Message::TimingClock,
+
+            // same packed snd_seq_ev_ext_t as in set_variable
+            SND_SEQ_EVENT_SYSEX => {
+                let bytes = unsafe {
+                    let ext = data as *const u8;
+                    let len = ptr::read_unaligned(ext as *const c_uint) as usize;
+                    let ptr = ptr::read_unaligned(ext.add(4) as *const *const u8);
+                    slice::from_raw_parts(ptr, len)
+                };
+
+                // F0 and F7 around the manufacturer and data, a three byte manufacturer
+                // starts with 0
+                let body = match bytes {
+                    [0xF0, body @ .., 0xF7] => body,
+                    _ => return None
+                };
+
+                match *body {
+                    [0, b1, b2, ref data @ ..] =>
+                        Message::SysEx(Manufacturer::ThreeByte(0, b1, b2), data.to_vec()),
+                    [id, ref data @ ..] =>
+                        Message::SysEx(Manufacturer::OneByte(id), data.to_vec()),
+                    [] => return None
+                }
+            },
+
+            _ => return None
+        };
+
+        Some(msg)
+    }
+}
@@ -21,8 +21,13 @@
 use std::ptr::null_mut;
 use std::ffi;
 
+use libc::{c_int, c_uint, pollfd, POLLIN};
+
+use midi::Message;
 use alsa_sys::*;
 
+use event::FromSndSeqEvent;
+
 use {
     SequencerHandle,
     SequencerPort,
@@ -95,6 +100,46 @@ impl SequencerHandle {
             snd_seq_drain_output(self.raw_handle);
         }
     }
+
+    /// The fds to poll for incoming events.
+    pub fn poll_fds(&self) -> Vec<c_int> {
+        unsafe {
+            let count = snd_seq_poll_descriptors_count(self.raw_handle, POLLIN);
+            let mut pfds = vec![pollfd { fd: -1, events: 0, revents: 0 }; count.max(0) as usize];
+
+            let count = snd_seq_poll_descriptors(self.raw_handle, pfds.as_mut_ptr(),
+                                                 pfds.len() as c_uint, POLLIN);
+            pfds.truncate(count.max(0) as usize);
+
+            pfds.iter().map(|pfd| pfd.fd).collect()
+        }
+    }
+
+    /// Reads the events that came in, each with the number of the port it came in on. Only
+    /// call it when one of `poll_fds` is readable, it blocks otherwise. Events that aren't a
+    /// `Message` are dropped.
+    pub fn receive(&self) -> Vec<(c_int, Message)> {
+        let mut received = vec![];
+
+        unsafe {
+            loop {
+                let mut ev = null_mut();
+                if snd_seq_event_input(self.raw_handle, &mut ev) < 0 || ev.is_null() {
+                    break;
+                }
+
+                if let Some(msg) = (*ev).to_message() {
+                    received.push(((*ev).dest.port as c_int, msg));
+                }
+
+                if snd_seq_event_input_pending(self.raw_handle, 0) <= 0 {
+                    break;
+                }
+            }
+        }
+
+        received
+    }
 }
 
 impl Drop for SequencerHandle {
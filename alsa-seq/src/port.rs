@@ -38,6 +38,7 @@ bitflags! {
         const PORT_CAPABILITY_DUPLEX = (1 << 4);
         const PORT_CAPABILITY_NO_EXPORT = (1 << 7);
         const PORT_CAPABILITY_READ = (1 << 0);
+        const PORT_CAPABILITY_WRITE = (1 << 1);
         const PORT_CAPABILITY_SUBS_READ = (1 << 5);
         const PORT_CAPABILITY_SUBS_WRITE = (1 << 6);
         const PORT_CAPABILITY_SYNC_READ = (1 << 2);
@@ -65,8 +66,14 @@ pub enum PortType {
 }
 
 impl<'handle> SequencerPort<'handle> {
+    /// The port's number, which `SequencerHandle::receive` tells incoming events apart by.
+    pub fn id(&self) -> c_int {
+        self.raw_handle
+    }
+
     pub fn send_message(&self, msg: &Message) -> Result<(), Error> {
-        let mut ev = match msg.to_snd_seq_event() {
+        let mut ext = vec![];
+        let mut ev = match msg.to_snd_seq_event(&mut ext) {
             Some(ev) => ev,
             None => return Err(Error::Unknown)
         };
@@ -3,3 +3,7 @@ echo build
 cargo build --release
 echo move picture to target folder
 cp picturetest.png target/release
+echo copy profile to target folder
+cp profile.toml target/release
+echo copy button mapping to target folder
+cp mapping.toml target/release
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::mem;
+
+use midi::{Channel, Message, U7};
+
+/// One recorded knob value, `position` is where in the sequencer's bar it happened, from 0 to 1.
+struct Point {
+    position: f32,
+    value: U7,
+    msgs: Vec<Message>,
+}
+
+/// Knob movements recorded against the internal sequencer's bar, looped back while it plays.
+/// Everything is kept per parameter, so a knob's automation survives switching pages.
+pub struct Automation {
+    lanes: BTreeMap<(Channel, U7), Vec<Point>>,
+
+    // where recording on a lane got to in this pass, it overwrites what was there before
+    recording: BTreeMap<(Channel, U7), f32>,
+    position: Option<f32>,
+}
+
+impl Automation {
+    pub fn new() -> Self {
+        Automation {
+            lanes: BTreeMap::new(),
+            recording: BTreeMap::new(),
+            position: None,
+        }
+    }
+
+    /// Records `msgs` for a parameter at the current position, nothing happens while the
+    /// sequencer is stopped.
+    pub fn record(&mut self, parameter: (Channel, U7), value: U7, msgs: Vec<Message>) {
+        let position = match self.position {
+            Some(position) => position,
+            None => return,
+        };
+
+        let lane = self.lanes.entry(parameter).or_default();
+
+        if let Some(&from) = self.recording.get(&parameter) {
+            if from <= position {
+                lane.retain(|point| point.position <= from || point.position > position);
+            } else {
+                lane.retain(|point| point.position <= from && point.position > position);
+            }
+        }
+
+        let idx = lane.iter().position(|point| point.position > position).unwrap_or(lane.len());
+        lane.insert(idx, Point { position, value, msgs });
+
+        self.recording.insert(parameter, position);
+    }
+
+    /// Recording for this pass is over, the parameters recorded in it play back again.
+    pub fn stop_recording(&mut self) {
+        self.recording.clear();
+    }
+
+    pub fn clear(&mut self, parameter: (Channel, U7)) {
+        self.lanes.remove(&parameter);
+        self.recording.remove(&parameter);
+    }
+
+    /// Moves on to `position`, `None` while the sequencer is stopped, and returns what was
+    /// recorded since the last call along with the value it sets its parameter to. Parameters
+    /// being recorded right now stay quiet.
+    pub fn advance(&mut self, position: Option<f32>) -> Vec<((Channel, U7), U7, Vec<Message>)> {
+        let (from, to) = match (mem::replace(&mut self.position, position), position) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return vec![],
+        };
+
+        let in_window = |point: &Point| {
+            if from <= to {
+                point.position > from && point.position <= to
+            } else {
+                point.position > from || point.position <= to
+            }
+        };
+
+        self.lanes
+            .iter()
+            .filter(|&(parameter, _)| !self.recording.contains_key(parameter))
+            .flat_map(|(&parameter, lane)| {
+                lane.iter()
+                    .filter(|point| in_window(point))
+                    .map(move |point| (parameter, point.value, point.msgs.clone()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use midi::Channel::Ch1;
+
+    const CUTOFF: (Channel, U7) = (Ch1, 74);
+
+    fn record(automation: &mut Automation, position: f32, value: U7) {
+        automation.advance(Some(position));
+        automation.record(CUTOFF, value, vec![Message::ControlChange(Ch1, 74, value)]);
+    }
+
+    fn values(played: Vec<((Channel, U7), U7, Vec<Message>)>) -> Vec<U7> {
+        played.into_iter().map(|(_, value, _)| value).collect()
+    }
+
+    #[test]
+    fn nothing_while_stopped() {
+        let mut automation = Automation::new();
+
+        automation.record(CUTOFF, 10, vec![]);
+        assert_eq!(values(automation.advance(Some(0.0))), vec![]);
+        assert_eq!(values(automation.advance(Some(1.0))), vec![]);
+    }
+
+    #[test]
+    fn plays_back_what_was_recorded() {
+        let mut automation = Automation::new();
+
+        record(&mut automation, 0.25, 10);
+        record(&mut automation, 0.5, 20);
+        automation.stop_recording();
+
+        assert_eq!(values(automation.advance(Some(0.6))), vec![]);
+        assert_eq!(values(automation.advance(Some(0.1))), vec![]);
+        assert_eq!(values(automation.advance(Some(0.25))), vec![10]);
+        assert_eq!(values(automation.advance(Some(0.75))), vec![20]);
+    }
+
+    #[test]
+    fn wraps_around_the_bar() {
+        let mut automation = Automation::new();
+
+        record(&mut automation, 0.05, 10);
+        record(&mut automation, 0.95, 20);
+        automation.stop_recording();
+
+        automation.advance(Some(0.9));
+        assert_eq!(values(automation.advance(Some(0.1))), vec![10, 20]);
+    }
+
+    #[test]
+    fn quiet_while_recording() {
+        let mut automation = Automation::new();
+
+        record(&mut automation, 0.25, 10);
+        assert_eq!(values(automation.advance(Some(0.5))), vec![]);
+        assert_eq!(values(automation.advance(Some(0.3))), vec![]);
+    }
+
+    #[test]
+    fn recording_again_overwrites() {
+        let mut automation = Automation::new();
+
+        record(&mut automation, 0.2, 10);
+        record(&mut automation, 0.4, 20);
+        record(&mut automation, 0.6, 30);
+        automation.stop_recording();
+
+        // the second pass goes from 0.1 to 0.5 and leaves 0.6 alone
+        record(&mut automation, 0.1, 40);
+        record(&mut automation, 0.5, 50);
+        automation.stop_recording();
+
+        automation.advance(Some(0.0));
+        assert_eq!(values(automation.advance(Some(0.99))), vec![40, 50, 30]);
+    }
+
+    #[test]
+    fn clear() {
+        let mut automation = Automation::new();
+
+        record(&mut automation, 0.25, 10);
+        automation.stop_recording();
+        automation.clear(CUTOFF);
+
+        automation.advance(Some(0.0));
+        assert_eq!(values(automation.advance(Some(0.99))), vec![]);
+    }
+}
@@ -19,7 +19,7 @@ use std::os::unix::io::RawFd;
 
 use midi::Message;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum MaschineButton {
     F8,
     F7,
@@ -88,152 +88,13 @@ pub enum MaschineButton {
     R7,
     R8,
 
-    A1,
-    A2,
-    A3,
-    A4,
-    A5,
-    A6,
-    A7,
-    A8,
-
-    B1,
-    B2,
-    B3,
-    B4,
-    B5,
-    B6,
-    B7,
-    B8,
-
-    C1,
-    C2,
-    C3,
-    C4,
-    C5,
-    C6,
-    C7,
-    C8,
-
-    D1,
-    D2,
-    D3,
-    D4,
-    D5,
-    D6,
-    D7,
-    D8,
-
-    E1,
-    E2,
-    E3,
-    E4,
-    E5,
-    E6,
-    E7,
-    E8,
-
-    FF1,
-    FF2,
-    FF3,
-    FF4,
-    FF5,
-    FF6,
-    FF7,
-    FF8,
-
-    G1,
-    G2,
-    G3,
-    G4,
-    G5,
-    G6,
-    G7,
-    G8,
-
-    H1,
-    H2,
-    H3,
-    H4,
-    H5,
-    H6,
-    H7,
-    H8,
-
-    I1,
-    I2,
-    I3,
-    I4,
-    I5,
-    I6,
-    I7,
-    I8,
-
-    J1,
-    J2,
-    J3,
-    J4,
-    J5,
-    J6,
-    J7,
-    J8,
-
-    K1,
-    K2,
-    K3,
-    K4,
-    K5,
-    K6,
-    K7,
-    K8,
-
-    L1,
-    L2,
-    L3,
-    L4,
-    L5,
-    L6,
-    L7,
-    L8,
-
-    N1,
-    N2,
-    N3,
-    N4,
-    N5,
-    N6,
-    N7,
-    N8,
-
-    M1,
-    M2,
-    M3,
-    M4,
-    M5,
-    M6,
-    M7,
-    M8,
-
-    O1,
-    O2,
-    O3,
-    O4,
-    O5,
-    O6,
-    O7,
-    O8,
-
-    P1,
-    P2,
-    P3,
-    P4,
-    P5,
-    P6,
-    P7,
-    P8,
+}
 
+/// Knob deltas are in these steps, a full turn of a knob is this many of them.
+pub const KNOB_STEPS_PER_TURN: i32 = 1000;
+/// Same for the encoder, which moves in detents.
+pub const ENCODER_STEPS_PER_TURN: i32 = 16;
 
-}
 pub trait Maschine {
     fn get_fd(&self) -> RawFd;
 
@@ -242,9 +103,6 @@ pub trait Maschine {
     fn get_midi_note_base(&self) -> u8;
     fn set_midi_note_base(&mut self, base: u8);
 
-    fn set_roller_state(&mut self, state: usize, idx: usize);
-    fn get_roller_state(&self, idx: usize) -> usize;
-
     fn set_pad_light(&mut self, pad_idx: usize, color: u32, brightness: f32);
     fn set_button_light(&mut self, btn: MaschineButton, color: u32, brightness: f32);
 
@@ -260,9 +118,6 @@ pub trait Maschine {
     fn set_seq_speed(&mut self, status: usize);
     fn get_seq_speed(&self) -> u64;
 
-    fn set_padmode(&mut self, state: usize);
-    fn get_padmode(&self) -> usize;
-
     fn set_playing(&mut self, state: usize);
     fn get_playing(&self) -> bool;
 
@@ -271,6 +126,9 @@ pub trait Maschine {
     fn clear_screen(&mut self);
     fn write_lights(&mut self);
     fn write_screen(&mut self);
+    /// Draws a line of text, 8 pixels high, over whatever is on that line of the screen. Lines
+    /// go out with the next `write_lights`, so drawing one is cheap.
+    fn write_text(&mut self, line: usize, text: &str);
 }
 
 #[allow(unused_variables)]
@@ -280,6 +138,7 @@ pub trait MaschineHandler {
     fn pad_released(&mut self, _: &mut dyn Maschine, pad_idx: usize) {}
 
     fn encoder_step(&mut self, _: &mut dyn Maschine, encoder_idx: usize, delta: i32) {}
+    fn knob_moved(&mut self, _: &mut dyn Maschine, knob_idx: usize, delta: i32) {}
 
     fn button_down(&mut self, _: &mut dyn Maschine, button: MaschineButton, byte: u8, is_down: bool) {}
     fn button_up(&mut self, _: &mut dyn Maschine, button: MaschineButton, byte: u8, is_down: bool) {}
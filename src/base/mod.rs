@@ -19,7 +19,9 @@ pub mod maschine;
 pub use self::maschine::{
     Maschine,
     MaschineHandler,
-    MaschineButton
+    MaschineButton,
+    ENCODER_STEPS_PER_TURN,
+    KNOB_STEPS_PER_TURN
 };
 
 pub mod maschine_pad;
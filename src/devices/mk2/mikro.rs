@@ -16,7 +16,7 @@
 //  <http://www.gnu.org/licenses/>.
 
 use std::fs::File;
-use std::mem::transmute;
+use std::mem::{self, transmute};
 use std::os::unix::io;
 
 extern crate nix;
@@ -28,10 +28,23 @@ extern crate hex;
 extern crate png;
 
 
-use base::{Maschine, MaschineButton, MaschineHandler, MaschinePad, MaschinePadStateTransition};
+use super::font;
 
+use base::{
+    Maschine, MaschineButton, MaschineHandler, MaschinePad, MaschinePadStateTransition, KNOB_STEPS_PER_TURN,
+};
+use utils::{BUTTON_LIGHT_ON, GROUP_BUTTONS};
 
-const BUTTON_REPORT_TO_MIKROBUTTONS_MAP: [[Option<MaschineButton>; 8]; 23] = [
+
+const SCREEN_WIDTH: usize = 256;
+// text lines are 8 pixels high
+const SCREEN_LINES: usize = 8;
+
+// the encoder byte has the encoder's position in its low nibble and the push in the bit above
+const ENCODER_POSITION_MASK: u8 = 0x0F;
+const ENCODER_PUSH_BIT: u8 = 0x10;
+
+const BUTTON_REPORT_TO_MIKROBUTTONS_MAP: [[Option<MaschineButton>; 8]; 7] = [
     [
         Some(MaschineButton::F8),
         Some(MaschineButton::F7),
@@ -102,166 +115,6 @@ const BUTTON_REPORT_TO_MIKROBUTTONS_MAP: [[Option<MaschineButton>; 8]; 23] = [
         Some(MaschineButton::R7),
         Some(MaschineButton::R8),
     ],
-    [
-        Some(MaschineButton::A1),
-        Some(MaschineButton::A2),
-        Some(MaschineButton::A3),
-        Some(MaschineButton::A4),
-        Some(MaschineButton::A5),
-        Some(MaschineButton::A6),
-        Some(MaschineButton::A7),
-        Some(MaschineButton::A8),
-    ],
-    [
-        Some(MaschineButton::B1),
-        Some(MaschineButton::B2),
-        Some(MaschineButton::B3),
-        Some(MaschineButton::B4),
-        Some(MaschineButton::B5),
-        Some(MaschineButton::B6),
-        Some(MaschineButton::B7),
-        Some(MaschineButton::B8),
-    ],
-    [
-        Some(MaschineButton::C1),
-        Some(MaschineButton::C2),
-        Some(MaschineButton::C3),
-        Some(MaschineButton::C4),
-        Some(MaschineButton::C5),
-        Some(MaschineButton::C6),
-        Some(MaschineButton::C7),
-        Some(MaschineButton::C8),
-    ],
-    [
-        Some(MaschineButton::D1),
-        Some(MaschineButton::D2),
-        Some(MaschineButton::D3),
-        Some(MaschineButton::D4),
-        Some(MaschineButton::D5),
-        Some(MaschineButton::D6),
-        Some(MaschineButton::D7),
-        Some(MaschineButton::D8),
-    ],
-    [
-        Some(MaschineButton::E1),
-        Some(MaschineButton::E2),
-        Some(MaschineButton::E3),
-        Some(MaschineButton::E4),
-        Some(MaschineButton::E5),
-        Some(MaschineButton::E6),
-        Some(MaschineButton::E7),
-        Some(MaschineButton::E8),
-    ],
-    [
-        Some(MaschineButton::FF1),
-        Some(MaschineButton::FF2),
-        Some(MaschineButton::FF3),
-        Some(MaschineButton::FF4),
-        Some(MaschineButton::FF5),
-        Some(MaschineButton::FF6),
-        Some(MaschineButton::FF7),
-        Some(MaschineButton::FF8),
-    ],
-    [
-        Some(MaschineButton::G1),
-        Some(MaschineButton::G2),
-        Some(MaschineButton::G3),
-        Some(MaschineButton::G4),
-        Some(MaschineButton::G5),
-        Some(MaschineButton::G6),
-        Some(MaschineButton::G7),
-        Some(MaschineButton::G8),
-    ],
-    [
-        Some(MaschineButton::H1),
-        Some(MaschineButton::H2),
-        Some(MaschineButton::H3),
-        Some(MaschineButton::H4),
-        Some(MaschineButton::H5),
-        Some(MaschineButton::H6),
-        Some(MaschineButton::H7),
-        Some(MaschineButton::H8),
-    ],
-    [
-        Some(MaschineButton::I1),
-        Some(MaschineButton::I2),
-        Some(MaschineButton::I3),
-        Some(MaschineButton::I4),
-        Some(MaschineButton::I5),
-        Some(MaschineButton::I6),
-        Some(MaschineButton::I7),
-        Some(MaschineButton::I8),
-    ],
-    [
-        Some(MaschineButton::J1),
-        Some(MaschineButton::J2),
-        Some(MaschineButton::J3),
-        Some(MaschineButton::J4),
-        Some(MaschineButton::J5),
-        Some(MaschineButton::J6),
-        Some(MaschineButton::J7),
-        Some(MaschineButton::J8),
-    ],
-    [
-        Some(MaschineButton::K1),
-        Some(MaschineButton::K2),
-        Some(MaschineButton::K3),
-        Some(MaschineButton::K4),
-        Some(MaschineButton::K5),
-        Some(MaschineButton::K6),
-        Some(MaschineButton::K7),
-        Some(MaschineButton::K8),
-    ],
-    [
-        Some(MaschineButton::L1),
-        Some(MaschineButton::L2),
-        Some(MaschineButton::L3),
-        Some(MaschineButton::L4),
-        Some(MaschineButton::L5),
-        Some(MaschineButton::L6),
-        Some(MaschineButton::L7),
-        Some(MaschineButton::L8),
-    ],
-    [
-        Some(MaschineButton::M1),
-        Some(MaschineButton::M2),
-        Some(MaschineButton::M3),
-        Some(MaschineButton::M4),
-        Some(MaschineButton::M5),
-        Some(MaschineButton::M6),
-        Some(MaschineButton::M7),
-        Some(MaschineButton::M8),
-    ],
-    [
-        Some(MaschineButton::N1),
-        Some(MaschineButton::N2),
-        Some(MaschineButton::N3),
-        Some(MaschineButton::N4),
-        Some(MaschineButton::N5),
-        Some(MaschineButton::N6),
-        Some(MaschineButton::N7),
-        Some(MaschineButton::N8),
-    ],
-    [
-        Some(MaschineButton::O1),
-        Some(MaschineButton::O2),
-        Some(MaschineButton::O3),
-        Some(MaschineButton::O4),
-        Some(MaschineButton::O5),
-        Some(MaschineButton::O6),
-        Some(MaschineButton::O7),
-        Some(MaschineButton::O8),
-    ],
-    [
-        Some(MaschineButton::P1),
-        Some(MaschineButton::P2),
-        Some(MaschineButton::P3),
-        Some(MaschineButton::P4),
-        Some(MaschineButton::P5),
-        Some(MaschineButton::P6),
-        Some(MaschineButton::P7),
-        Some(MaschineButton::P8),
-    ],
 ];
 
 #[allow(dead_code)]
@@ -275,14 +128,18 @@ pub struct Mikro {
     light_buf: [u8; 49],
     light_buf2: [u8; 32],
     light_buf3: [u8; 57],
+    // the text lines as they'll be on the screen, the dirty ones go out with the lights
+    text: [[[u8; SCREEN_WIDTH / 8]; 8]; SCREEN_LINES],
+    text_dirty: [bool; SCREEN_LINES],
 
     pads: [MaschinePad; 16],
-    buttons: [u8; 24],
+    buttons: [u8; 7],
+    encoder: u8,
+    encoder_pushed: bool,
+    knobs: [Option<i32>; 8],
 
     midi_note_base: u8,
-    roller_state: [usize; 9],
     mod_state: usize,
-    padmode: usize,
 
     note: [u8; 16],
     note_state: [usize; 16],
@@ -322,17 +179,17 @@ impl Mikro {
             light_buf: [0u8; 49],
             light_buf2: [0u8; 32],
             light_buf3: [0u8; 57],
+            text: [[[0u8; SCREEN_WIDTH / 8]; 8]; SCREEN_LINES],
+            text_dirty: [false; SCREEN_LINES],
 
             pads: Mikro::sixteen_maschine_pads(),
-            buttons: [
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10,
-                0x10, 0x10, 0x10,
-            ],
+            buttons: [0u8; 7],
+            encoder: 0x10,
+            encoder_pushed: false,
+            knobs: [None; 8],
 
             midi_note_base: 48,
-            roller_state: [0usize; 9],
             mod_state: 0,
-            padmode: 0,
 
             note: [48u8; 16],
             note_state: [0usize; 16],
@@ -352,7 +209,7 @@ impl Mikro {
     }
 
     fn read_buttons(&mut self, handler: &mut dyn MaschineHandler, buf: &[u8]) {
-        for (idx, &byte) in buf[0..23].iter().enumerate() {
+        for (idx, &byte) in buf[0..7].iter().enumerate() {
             let mut diff = (byte ^ self.buttons[idx]) as u32;
 
             let mut off = 0usize;
@@ -376,20 +233,58 @@ impl Mikro {
             self.buttons[idx] = byte;
         }
 
-        if self.buttons[23] > 0xF {
-            self.buttons[23] = buf[23];
-            return;
-        } else if self.buttons[23] == buf[23] {
+        let (encoder, push) = encoder_byte(buf[7]);
+        self.read_encoder_push(handler, push);
+        self.read_encoder(handler, encoder);
+        self.read_knobs(handler, &buf[8..24]);
+    }
+
+    /// The push goes out like any other button, as `MaschineButton::Encoder`.
+    fn read_encoder_push(&mut self, handler: &mut dyn MaschineHandler, push: u8) {
+        let pushed = push != 0;
+        if pushed == self.encoder_pushed {
             return;
         }
 
-        if ((self.buttons[23] + 1) & 0xF) == buf[23] {
-            handler.encoder_step(self, 0, 1);
+        self.encoder_pushed = pushed;
+
+        if pushed {
+            handler.button_down(self, MaschineButton::Encoder, push, true);
         } else {
-            handler.encoder_step(self, 0, -1);
+            handler.button_up(self, MaschineButton::Encoder, push, false);
         }
+    }
+
+    fn read_encoder(&mut self, handler: &mut dyn MaschineHandler, encoder: u8) {
+        if self.encoder > 0xF {
+            self.encoder = encoder;
+            return;
+        } else if self.encoder == encoder {
+            return;
+        }
+
+        let delta = encoder_delta(self.encoder, encoder);
+        handler.encoder_step(self, 0, delta);
 
-        self.buttons[23] = buf[23];
+        self.encoder = encoder;
+    }
+
+    /// The knobs report where they are within a turn, as a little-endian u16 each. The first
+    /// report only tells us where they start.
+    fn read_knobs(&mut self, handler: &mut dyn MaschineHandler, buf: &[u8]) {
+        for (idx, raw) in buf.chunks(2).enumerate() {
+            let position = knob_position(raw);
+
+            let last = match self.knobs[idx].replace(position) {
+                Some(last) => last,
+                None => continue,
+            };
+
+            let delta = knob_delta(last, position);
+            if delta != 0 {
+                handler.knob_moved(self, idx, delta);
+            }
+        }
     }
 
     fn read_pads(&mut self, handler: &mut dyn MaschineHandler, buf: &[u8]) {
@@ -411,6 +306,41 @@ impl Mikro {
     }
 }
 
+/// The encoder's position and push bits out of byte 7 of the button report.
+fn encoder_byte(byte: u8) -> (u8, u8) {
+    (byte & ENCODER_POSITION_MASK, byte & ENCODER_PUSH_BIT)
+}
+
+/// How far the encoder went. A fast spin can skip positions between reports, so it's the
+/// shortest way round the 16 positions rather than a single step.
+fn encoder_delta(last: u8, position: u8) -> i32 {
+    let delta = (position as i32 - last as i32) & 0xF;
+
+    if delta > 8 {
+        delta - 16
+    } else {
+        delta
+    }
+}
+
+fn knob_position(raw: &[u8]) -> i32 {
+    ((raw[0] as i32) | ((raw[1] as i32) << 8)) % KNOB_STEPS_PER_TURN
+}
+
+/// The shortest way from the last position is the way the knob went, which also covers
+/// crossing from the end of a turn back to its start.
+fn knob_delta(last: i32, position: i32) -> i32 {
+    let delta = position - last;
+
+    if delta > KNOB_STEPS_PER_TURN / 2 {
+        delta - KNOB_STEPS_PER_TURN
+    } else if delta < -KNOB_STEPS_PER_TURN / 2 {
+        delta + KNOB_STEPS_PER_TURN
+    } else {
+        delta
+    }
+}
+
 fn set_rgb_light(rgb: &mut [u8], color: u32, brightness: f32) {
     let brightness = brightness * 0.5;
 
@@ -419,6 +349,30 @@ fn set_rgb_light(rgb: &mut [u8], color: u32, brightness: f32) {
     rgb[2] = (brightness * (((color) & 0xFF) as f32)) as u8;
 }
 
+impl Mikro {
+    /// Sends the text lines that changed, each in one report of its 8 rows.
+    fn flush_text(&mut self) {
+        let mut screen_buf = [0u8; 1 + 8 + 512];
+        screen_buf[0] = 0xE0;
+        screen_buf[5] = 0x08;
+        screen_buf[7] = 0x20;
+
+        for line in 0..SCREEN_LINES {
+            if !mem::replace(&mut self.text_dirty[line], false) {
+                continue;
+            }
+
+            screen_buf[3] = (line * 8) as u8;
+            for (y, row) in self.text[line].iter().enumerate() {
+                let offset = 9 + y * row.len();
+                screen_buf[offset..(offset + row.len())].copy_from_slice(row);
+            }
+
+            unistd::write(self.dev, &screen_buf).unwrap();
+        }
+    }
+}
+
 impl Maschine for Mikro {
     fn get_fd(&self) -> io::RawFd {
         return self.dev;
@@ -428,6 +382,8 @@ impl Maschine for Mikro {
         unistd::write(self.dev, &self.light_buf).unwrap();
         unistd::write(self.dev, &self.light_buf2).unwrap();
         unistd::write(self.dev, &self.light_buf3).unwrap();
+
+        self.flush_text();
     }
 
     fn set_pad_light(&mut self, pad: usize, color: u32, brightness: f32) {
@@ -445,16 +401,6 @@ impl Maschine for Mikro {
         return self.midi_note_base;
     }
 
-    fn set_roller_state(&mut self, state: usize, idx: usize) {
-        self.roller_state[idx] = state;
-    }
-
-    fn get_roller_state(&self, idx: usize) -> usize {
-        //println!("{}", self.roller_state[idx]);
-
-        return self.roller_state[idx];
-    }
-
     fn set_mod(&mut self, state: usize) {
         self.mod_state = state;
     }
@@ -463,26 +409,6 @@ impl Maschine for Mikro {
         return self.mod_state;
     }
 
-    fn set_padmode(&mut self, state: usize) {
-        if self.padmode < 3 && state == 1 {
-            self.padmode += 1
-        } else {
-            self.padmode = 0;
-        };
-        println!("Padmode {}", self.padmode);
-        if self.padmode == 2 {
-            println!("This is Sequencer mode");
-            println!("");
-            println!("Tapping on pads activates them for the sequence.");
-            println!("Tapping on a pad while holding shift, then pressing another pad");
-            println!("will change the note of the pad you pressed first");
-        }
-    }
-
-    fn get_padmode(&self) -> usize {
-        return self.padmode;
-    }
-
     fn set_playing(&mut self, state: usize) {
         if state == 1 {
             self.playing = true;
@@ -537,7 +463,17 @@ impl Maschine for Mikro {
         return self.speed
     }
 
-    fn set_button_light(&mut self, btn: MaschineButton, _color: u32, brightness: f32) {
+    fn set_button_light(&mut self, btn: MaschineButton, color: u32, brightness: f32) {
+        // the group buttons have two RGB LEDs each, H's first
+        if let Some(group) = GROUP_BUTTONS.iter().position(|&group| group == btn) {
+            let offset = 1 + (7 - group) * 6;
+
+            for rgb in self.light_buf3[offset..(offset + 6)].chunks_mut(3) {
+                set_rgb_light(rgb, color, brightness / BUTTON_LIGHT_ON);
+            }
+            return;
+        }
+
         let mut idx = 0;
         let mut idx2 = 0;
         match btn {
@@ -577,15 +513,7 @@ impl Maschine for Mikro {
             MaschineButton::Step => idx = 30,
             MaschineButton::Browse => idx = 31,
 
-            MaschineButton::GroupH => idx2 = 2,
-            MaschineButton::GroupG => idx2 = 9,
-            MaschineButton::GroupF => idx2 = 14,
-            MaschineButton::GroupE => idx2 = 22,
-            MaschineButton::GroupD => idx2 = 26,
-            MaschineButton::GroupC => idx2 = 34,
-            MaschineButton::GroupB => idx2 = 39,
-            MaschineButton::GroupA => idx2 = 48,
-            MaschineButton::Shift => idx2 = 47,
+            MaschineButton::Shift => idx2 = 55,
             MaschineButton::Erase => idx2 = 56,
             MaschineButton::Rec => idx2 = 54,
             MaschineButton::Play => idx2 = 53,
@@ -776,4 +704,82 @@ impl Maschine for Mikro {
         }
         println!("RUNNING!");
     }
+
+    fn write_text(&mut self, line: usize, text: &str) {
+        if line >= SCREEN_LINES {
+            return;
+        }
+
+        let mut rows = [[0u8; SCREEN_WIDTH / 8]; 8];
+
+        for (char_idx, c) in text.chars().take(SCREEN_WIDTH / 6).enumerate() {
+            for (col, bits) in font::glyph(c).iter().enumerate() {
+                let x = char_idx * 6 + col;
+
+                for (y, row) in rows.iter_mut().enumerate() {
+                    if bits & (1 << y) != 0 {
+                        row[x / 8] |= 0x80 >> (x % 8);
+                    }
+                }
+            }
+        }
+
+        if self.text[line] != rows {
+            self.text[line] = rows;
+            self.text_dirty[line] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoder_and_push() {
+        assert_eq!(encoder_byte(0x00), (0, 0));
+        assert_eq!(encoder_byte(0x0F), (15, 0));
+        assert_eq!(encoder_byte(0x13), (3, ENCODER_PUSH_BIT));
+        // the bits above aren't the encoder's
+        assert_eq!(encoder_byte(0xE5), (5, 0));
+    }
+
+    #[test]
+    fn encoder_steps() {
+        assert_eq!(encoder_delta(3, 4), 1);
+        assert_eq!(encoder_delta(4, 3), -1);
+        assert_eq!(encoder_delta(3, 6), 3);
+    }
+
+    #[test]
+    fn encoder_wraps_the_shortest_way() {
+        assert_eq!(encoder_delta(15, 0), 1);
+        assert_eq!(encoder_delta(0, 15), -1);
+        assert_eq!(encoder_delta(14, 2), 4);
+        assert_eq!(encoder_delta(0, 8), 8);
+        assert_eq!(encoder_delta(0, 9), -7);
+    }
+
+    #[test]
+    fn knob_positions() {
+        assert_eq!(knob_position(&[0x00, 0x00]), 0);
+        assert_eq!(knob_position(&[0xE7, 0x03]), 999);
+        // past a turn is the same place in the next one
+        assert_eq!(knob_position(&[0xE8, 0x03]), 0);
+        assert_eq!(knob_position(&[0x1A, 0x04]), 50);
+    }
+
+    #[test]
+    fn knob_steps() {
+        assert_eq!(knob_delta(100, 112), 12);
+        assert_eq!(knob_delta(112, 100), -12);
+        assert_eq!(knob_delta(0, 500), 500);
+    }
+
+    #[test]
+    fn knob_wraps_the_shortest_way() {
+        assert_eq!(knob_delta(995, 5), 10);
+        assert_eq!(knob_delta(5, 995), -10);
+        assert_eq!(knob_delta(0, 501), -499);
+    }
 }
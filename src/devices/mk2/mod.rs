@@ -15,6 +15,7 @@
 //  License along with this program.  If not, see
 //  <http://www.gnu.org/licenses/>.
 
+mod font;
 mod mikro;
 
 pub use self::mikro::Mikro;
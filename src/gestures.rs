@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use base::MaschineButton;
+use osc::osc_button_to_btn_map;
+
+/// How long presses take to count as gestures, the [gestures] table of mapping.toml.
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct GestureTiming {
+    #[serde(default = "default_long_press_ms")]
+    pub long_press_ms: u64,
+    #[serde(default = "default_double_tap_ms")]
+    pub double_tap_ms: u64,
+}
+
+fn default_long_press_ms() -> u64 {
+    500
+}
+
+fn default_double_tap_ms() -> u64 {
+    300
+}
+
+impl Default for GestureTiming {
+    fn default() -> Self {
+        GestureTiming {
+            long_press_ms: default_long_press_ms(),
+            double_tap_ms: default_double_tap_ms(),
+        }
+    }
+}
+
+/// A button or a pad.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Control {
+    Button(MaschineButton),
+    Pad(usize),
+}
+
+impl Control {
+    /// Buttons are named like in the OSC API, pads are pad_0 to pad_15.
+    pub fn from_name(name: &str) -> Option<Control> {
+        match name.strip_prefix("pad_").map(str::parse) {
+            Some(Ok(pad_idx @ 0..=15)) => Some(Control::Pad(pad_idx)),
+            Some(_) => None,
+            None => osc_button_to_btn_map(name).map(Control::Button),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Gesture {
+    /// Held down for long_press_ms.
+    LongPress(MaschineButton),
+    /// Pressed again within double_tap_ms of the last press.
+    DoubleTap(MaschineButton),
+    /// The control pressed while the button is held, like Select+Group A or Erase+pad.
+    Combo(MaschineButton, Control),
+}
+
+/// Picks gestures out of the presses and releases on their way to the handler. Plain presses
+/// still happen, a gesture starts on top of them and ends when its control is let go.
+pub struct Gestures {
+    timing: GestureTiming,
+
+    held: BTreeMap<MaschineButton, Instant>,
+    // held buttons that were used for something else, they don't make long presses
+    consumed: BTreeSet<MaschineButton>,
+    last_press: Option<(MaschineButton, Instant)>,
+    // the gestures started so far and the control whose release ends each
+    active: Vec<(Control, Gesture)>,
+}
+
+impl Gestures {
+    pub fn new(timing: GestureTiming) -> Self {
+        Gestures {
+            timing,
+
+            held: BTreeMap::new(),
+            consumed: BTreeSet::new(),
+            last_press: None,
+            active: vec![],
+        }
+    }
+
+    /// The gestures a press starts. Shift has a layer of its own, it doesn't start combos.
+    pub fn press(&mut self, control: Control, now: Instant) -> Vec<Gesture> {
+        let mut started: Vec<_> = self
+            .held
+            .keys()
+            .filter(|&&held| held != MaschineButton::Shift && Control::Button(held) != control)
+            .map(|&held| Gesture::Combo(held, control))
+            .collect();
+
+        if let Control::Button(btn) = control {
+            let double_tap = Duration::from_millis(self.timing.double_tap_ms);
+
+            match self.last_press {
+                Some((last, at)) if last == btn && now.duration_since(at) <= double_tap => {
+                    started.push(Gesture::DoubleTap(btn));
+                    self.last_press = None;
+                }
+                _ => self.last_press = Some((btn, now)),
+            }
+
+            self.held.insert(btn, now);
+        }
+
+        self.active.extend(started.iter().map(|&gesture| (control, gesture)));
+        started
+    }
+
+    /// The gestures a release ends.
+    pub fn release(&mut self, control: Control) -> Vec<Gesture> {
+        if let Control::Button(btn) = control {
+            self.held.remove(&btn);
+            self.consumed.remove(&btn);
+        }
+
+        let (ended, active): (Vec<_>, Vec<_>) = self.active.drain(..).partition(|&(active, _)| active == control);
+        self.active = active;
+
+        ended.into_iter().map(|(_, gesture)| gesture).collect()
+    }
+
+    /// A held button did something else, like the held half of a combo or recording
+    /// automation. It doesn't become a long press and pressing it again isn't a double tap, it
+    /// still starts combos.
+    pub fn consume(&mut self, btn: MaschineButton) {
+        if self.held.contains_key(&btn) {
+            self.consumed.insert(btn);
+        }
+
+        if self.last_press.map(|(last, _)| last) == Some(btn) {
+            self.last_press = None;
+        }
+    }
+
+    /// The long presses that are due by `now`, the event loop calls this all the time.
+    pub fn poll(&mut self, now: Instant) -> Vec<Gesture> {
+        let long_press = Duration::from_millis(self.timing.long_press_ms);
+
+        let started: Vec<_> = self
+            .held
+            .iter()
+            .filter(|&(btn, &since)| now.duration_since(since) >= long_press && !self.consumed.contains(btn))
+            .map(|(&btn, _)| (Control::Button(btn), Gesture::LongPress(btn)))
+            .filter(|started| !self.active.contains(started))
+            .collect();
+
+        self.active.extend(started.iter().cloned());
+        started.into_iter().map(|(_, gesture)| gesture).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use base::MaschineButton::{GroupA, Play, Rec, Select, Shift};
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn long_press() {
+        let start = Instant::now();
+        let mut gestures = Gestures::new(GestureTiming::default());
+
+        assert_eq!(gestures.press(Control::Button(Play), start), vec![]);
+        assert_eq!(gestures.poll(start + ms(499)), vec![]);
+        assert_eq!(gestures.poll(start + ms(500)), vec![Gesture::LongPress(Play)]);
+
+        // only once while it's held
+        assert_eq!(gestures.poll(start + ms(600)), vec![]);
+        assert_eq!(gestures.release(Control::Button(Play)), vec![Gesture::LongPress(Play)]);
+        assert_eq!(gestures.poll(start + ms(2000)), vec![]);
+    }
+
+    #[test]
+    fn short_press_is_no_long_press() {
+        let start = Instant::now();
+        let mut gestures = Gestures::new(GestureTiming::default());
+
+        gestures.press(Control::Button(Play), start);
+        assert_eq!(gestures.release(Control::Button(Play)), vec![]);
+        assert_eq!(gestures.poll(start + ms(1000)), vec![]);
+    }
+
+    #[test]
+    fn double_tap() {
+        let start = Instant::now();
+        let mut gestures = Gestures::new(GestureTiming::default());
+
+        gestures.press(Control::Button(Play), start);
+        gestures.release(Control::Button(Play));
+        assert_eq!(gestures.press(Control::Button(Play), start + ms(300)), vec![Gesture::DoubleTap(Play)]);
+        assert_eq!(gestures.release(Control::Button(Play)), vec![Gesture::DoubleTap(Play)]);
+
+        // a third tap starts over
+        assert_eq!(gestures.press(Control::Button(Play), start + ms(400)), vec![]);
+    }
+
+    #[test]
+    fn slow_or_other_taps() {
+        let start = Instant::now();
+        let mut gestures = Gestures::new(GestureTiming::default());
+
+        gestures.press(Control::Button(Play), start);
+        gestures.release(Control::Button(Play));
+        assert_eq!(gestures.press(Control::Button(Play), start + ms(301)), vec![]);
+        gestures.release(Control::Button(Play));
+
+        assert_eq!(gestures.press(Control::Button(Rec), start + ms(350)), vec![]);
+    }
+
+    #[test]
+    fn combo() {
+        let start = Instant::now();
+        let mut gestures = Gestures::new(GestureTiming::default());
+
+        gestures.press(Control::Button(Select), start);
+        assert_eq!(gestures.press(Control::Button(GroupA), start), vec![Gesture::Combo(Select, Control::Button(GroupA))]);
+        assert_eq!(
+            gestures.release(Control::Button(GroupA)),
+            vec![Gesture::Combo(Select, Control::Button(GroupA))]
+        );
+
+        // it ends with the control that was pressed, not the held button
+        assert_eq!(gestures.press(Control::Pad(3), start), vec![Gesture::Combo(Select, Control::Pad(3))]);
+        assert_eq!(gestures.release(Control::Button(Select)), vec![]);
+        assert_eq!(gestures.release(Control::Pad(3)), vec![Gesture::Combo(Select, Control::Pad(3))]);
+    }
+
+    #[test]
+    fn shift_is_no_combo() {
+        let start = Instant::now();
+        let mut gestures = Gestures::new(GestureTiming::default());
+
+        gestures.press(Control::Button(Shift), start);
+        assert_eq!(gestures.press(Control::Pad(0), start), vec![]);
+    }
+
+    #[test]
+    fn consumed() {
+        let start = Instant::now();
+        let mut gestures = Gestures::new(GestureTiming::default());
+
+        gestures.press(Control::Button(Rec), start);
+        gestures.consume(Rec);
+        assert_eq!(gestures.poll(start + ms(1000)), vec![]);
+
+        // it's still held for combos, and comes back once it's let go
+        assert_eq!(gestures.press(Control::Pad(0), start), vec![Gesture::Combo(Rec, Control::Pad(0))]);
+        gestures.release(Control::Button(Rec));
+        assert_eq!(gestures.press(Control::Button(Rec), start + ms(1100)), vec![]);
+        assert_eq!(gestures.poll(start + ms(1600)), vec![Gesture::LongPress(Rec)]);
+    }
+
+    #[test]
+    fn consumed_is_no_double_tap() {
+        let start = Instant::now();
+        let mut gestures = Gestures::new(GestureTiming::default());
+
+        gestures.press(Control::Button(Select), start);
+        gestures.consume(Select);
+        gestures.release(Control::Button(Select));
+        assert_eq!(gestures.press(Control::Button(Select), start + ms(100)), vec![]);
+    }
+
+    #[test]
+    fn control_names() {
+        assert_eq!(Control::from_name("pad_15"), Some(Control::Pad(15)));
+        assert_eq!(Control::from_name("pad_16"), None);
+        assert_eq!(Control::from_name("select"), Some(Control::Button(Select)));
+    }
+}
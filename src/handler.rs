@@ -1,5 +1,7 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
 use std::net::{SocketAddr, UdpSocket};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use alsa_seq::*;
 
 extern crate nix;
@@ -11,8 +13,23 @@ use nix::poll::*;
 use hsl::HSL;
 use midi::*;
 use nix::poll::PollFd;
-use base::{Maschine, MaschineButton, MaschineHandler};
-use utils::{PressureShape, PAD_NOTE_MAP, PAD_RELEASED_BRIGHTNESS};
+use automation::Automation;
+use base::{Maschine, MaschineButton, MaschineHandler, ENCODER_STEPS_PER_TURN};
+use gestures::{Control, Gesture, Gestures};
+use knobs::{Acceleration, EncoderPush, Knob, KnobEncoding, KnobFunction, KnobFunctions, KnobPage, Takeover};
+use lights::{Animation, Beat, Lit};
+use macros::Macros;
+use mackie::Mackie;
+use mapping::{ButtonAction, InternalAction, Layer, Mapping};
+use midi_out::MidiOut;
+use profile::Profile;
+use strip::PitchStrip;
+use modes::{ModeContext, PadMode, PadModeHandler, PadModes};
+use utils::{
+    bpm_to_seq_speed, check_velocity_zones, mpe_configuration_msgs, BankSelect, PadControl, PadRelease, PressureShape, VelocityZone,
+    BUTTON_LIGHT_OFF, BUTTON_LIGHT_ON, ENCODER_CC, ENCODER_PUSH_CC, GROUP_BUTTONS, MIDI_CHANNELS, MPE_MEMBER_CHANNELS,
+    PAD_RELEASED_BRIGHTNESS, TAP_TEMPO_TAPS, TAP_TEMPO_TIMEOUT,
+};
 
 
 use std::os::unix::io::AsRawFd;
@@ -34,11 +51,55 @@ pub struct MHandler<'a> {
     pub send_aftertouch: bool,
     pub osc_socket: &'a UdpSocket,
     pub osc_outgoing_addr: SocketAddr,
+
+    pub mapping: Mapping,
+    gestures: Gestures,
+    // pads pressed with Shift held or in a combo, they play their mapping until they're let go
+    mapped_pads: [bool; 16],
+    macros: Macros,
+    pub modes: PadModes,
+    pad_mode_held: bool,
+    // buttons whose press the pad mode took, their release is the mode's too
+    mode_buttons: BTreeSet<MaschineButton>,
+
+    pub encoder: Knob,
+    pub encoder_push: EncoderPush,
+    pub knob_pages: Vec<KnobPage>,
+    knob_page: usize,
+    last_knob: Option<usize>,
+    knob_function: KnobFunction,
+    pub knob_functions: KnobFunctions,
+    tempo_taps: Vec<Instant>,
+    // the last value each absolute CC was sent with, for soft takeover
+    parameter_values: BTreeMap<(Channel, U7), U7>,
+
+    pub pitch_strip: PitchStrip,
+
+    /// The port the DAW lights the controls through, see the [feedback] tables of mapping.toml.
+    pub feedback_port: Option<i32>,
+    // the lights it set, pads get theirs back when they're let go
+    lit: BTreeMap<Control, Lit>,
+    // what flashing and pulsing lights keep time with
+    beat: Beat,
+
+    /// The Mackie Control surface, when the profile turns it on.
+    pub mackie: Option<Mackie<'a>>,
+
+    pub automation: Automation,
+    rec_held: bool,
+    rec_armed: bool,
+    erase_held: bool,
 }
 
 impl<'a> MHandler<'a> {
-    pub fn new(seq_handle: &'a SequencerHandle, seq_port: &'a SequencerPort<'a>, osc_socket: &'a UdpSocket) -> Self {
-        MHandler {
+    pub fn new(
+        seq_handle: &'a SequencerHandle,
+        seq_port: &'a SequencerPort<'a>,
+        osc_socket: &'a UdpSocket,
+        profile: &Profile,
+        mapping: Mapping,
+    ) -> Self {
+        let mut mhandler = MHandler {
             color: HSL { h: 0.0, s: 1.0, l: 0.3 },
             seq_handle,
             seq_port,
@@ -46,7 +107,55 @@ impl<'a> MHandler<'a> {
             send_aftertouch: false,
             osc_socket,
             osc_outgoing_addr: SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 42435)),
+
+            gestures: Gestures::new(mapping.gesture_timing),
+            mapping,
+            mapped_pads: [false; 16],
+            macros: Macros::new(),
+            modes: PadModes::new(),
+            pad_mode_held: false,
+            mode_buttons: BTreeSet::new(),
+
+            encoder: Knob::new(ENCODER_CC, ENCODER_STEPS_PER_TURN),
+            encoder_push: profile.encoder_push,
+            knob_pages: profile.knob_pages(),
+            knob_page: 0,
+            last_knob: None,
+            knob_function: KnobFunction::Pages,
+            knob_functions: KnobFunctions::new(),
+            tempo_taps: vec![],
+            parameter_values: BTreeMap::new(),
+
+            pitch_strip: PitchStrip::new(),
+
+            feedback_port: None,
+            lit: BTreeMap::new(),
+            beat: Beat::new(),
+
+            mackie: None,
+
+            automation: Automation::new(),
+            rec_held: false,
+            rec_armed: false,
+            erase_held: false,
+        };
+
+        // a click is one relative tick, like on a DAW controller's encoder
+        mhandler.encoder.ticks_per_step = 1.0;
+        mhandler.modes.snapshots.set_snapshots(profile.snapshots.clone());
+        mhandler.modes.session.set_config(mhandler.mapping.session.clone());
+        mhandler.pitch_strip.set_row(profile.pitch_strip_row);
+
+        for (pad_idx, zones) in profile.velocity_zones() {
+            mhandler.modes.notes.set_velocity_zones(pad_idx, zones);
+        }
+
+        // the MPE zone is set up once, the other modes play on its manager channel meanwhile
+        for msg in mpe_configuration_msgs(MPE_MEMBER_CHANNELS.len() as U7) {
+            mhandler.send_midi_msg(&msg);
         }
+
+        mhandler
     }
 
     pub fn pad_color(&self) -> u32 {
@@ -55,12 +164,431 @@ impl<'a> MHandler<'a> {
         ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
     }
 
-    pub fn pressure_to_vel(&self, pressure: f32) -> U7 {
-        (match self.pressure_shape {
-            PressureShape::Linear => pressure,
-            PressureShape::Exponential(power) => pressure.powf(power),
-            PressureShape::Constant(c_pressure) => c_pressure,
-        } * 127.0) as U7
+    pub fn midi_out(&self) -> MidiOut<'a> {
+        MidiOut {
+            seq_handle: self.seq_handle,
+            seq_port: self.seq_port,
+        }
+    }
+
+    pub fn send_midi_msg(&self, msg: &Message) {
+        self.midi_out().send(msg);
+    }
+
+    /// Hands out the pad modes along with what they need from the handler.
+    pub fn split_modes(&mut self) -> (ModeContext<'_>, &mut PadModes) {
+        let ctx = ModeContext {
+            midi: self.midi_out(),
+            color: self.pad_color(),
+            pressure_shape: self.pressure_shape,
+            send_aftertouch: self.send_aftertouch,
+            knob_pages: &mut self.knob_pages,
+            parameter_values: &mut self.parameter_values,
+        };
+
+        (ctx, &mut self.modes)
+    }
+
+    pub fn switch_pad_mode(&mut self, maschine: &mut dyn Maschine, mode: PadMode) {
+        let (mut ctx, modes) = self.split_modes();
+        modes.switch_to(&mut ctx, maschine, mode);
+    }
+
+    /// Gives the active pad mode the first go at a button press. Holding Pad Mode and pressing a
+    /// group button jumps straight to a mode.
+    fn pad_mode_button_down(&mut self, maschine: &mut dyn Maschine, btn: MaschineButton) -> bool {
+        if self.pad_mode_held {
+            if let Some(mode_idx) = GROUP_BUTTONS.iter().position(|&group| group == btn) {
+                if let Some(&mode) = PadMode::ALL.get(mode_idx) {
+                    self.switch_pad_mode(maschine, mode);
+                }
+                return true;
+            }
+        }
+
+        let (mut ctx, modes) = self.split_modes();
+        modes.current_mut().button_down(&mut ctx, maschine, btn)
+    }
+
+    /// Gives the Mackie Control surface the buttons that are MCU buttons.
+    fn mackie_button(&mut self, maschine: &mut dyn Maschine, btn: MaschineButton, is_down: bool) -> bool {
+        let handled = match self.mackie {
+            Some(ref mut mackie) => mackie.button(btn, is_down),
+            None => false,
+        };
+
+        if handled {
+            self.show_mackie(maschine);
+        }
+
+        handled
+    }
+
+    /// Reads what came in on the MIDI ports.
+    pub fn recv_midi_msgs(&mut self, maschine: &mut dyn Maschine) {
+        for (port, msg) in self.seq_handle.receive() {
+            if Some(port) == self.feedback_port {
+                self.receive_feedback(maschine, &msg);
+            }
+
+            if let Some(ref mut mackie) = self.mackie {
+                if port == mackie.port() {
+                    mackie.receive(&msg);
+                }
+            }
+        }
+
+        self.show_mackie(maschine);
+    }
+
+    /// Lights what the mapping's feedback says for the message. CCs are also where their
+    /// parameter is now, for the knobs' takeover, and clock is what the lights keep time with.
+    /// Notes also tell session mode what its clips and tracks are doing.
+    fn receive_feedback(&mut self, maschine: &mut dyn Maschine, msg: &Message) {
+        match *msg {
+            Message::ControlChange(channel, cc, value) => self.set_parameter_value((channel, cc), value),
+            Message::TimingClock => self.beat.clock(Instant::now()),
+            Message::Start => self.beat.start(),
+            _ => {}
+        }
+
+        let (ctx, modes) = self.split_modes();
+        if modes.session.receive(msg) && modes.current() == PadMode::Session {
+            modes.session.render_lights(&ctx, maschine);
+        }
+
+        let pad_color = self.pad_color();
+        let phase = self.beat.phase(Instant::now());
+
+        for (control, feedback, value, animation) in self.mapping.feedback(msg) {
+            let (color, brightness) = feedback.light(value, pad_color);
+            let lit = Lit::new(color, brightness, animation, self.lit.get(&control).cloned());
+
+            self.lit.insert(control, lit);
+            show_lit(maschine, control, lit.at(phase));
+        }
+    }
+
+    /// Moves the flashing and pulsing lights along to where they are in the beat.
+    pub fn animate_lights(&mut self, maschine: &mut dyn Maschine) {
+        let phase = self.beat.phase(Instant::now());
+
+        for (&control, lit) in &self.lit {
+            if lit.animation != Animation::Static {
+                show_lit(maschine, control, lit.at(phase));
+            }
+        }
+    }
+
+    /// Lights the buttons and draws the screen lines the DAW has changed on the Mackie Control
+    /// surface.
+    fn show_mackie(&mut self, maschine: &mut dyn Maschine) {
+        let mackie = match self.mackie {
+            Some(ref mut mackie) => mackie,
+            None => return,
+        };
+
+        for (btn, lit) in mackie.lights() {
+            maschine.set_button_light(btn, 0xFFFFFF, if lit { BUTTON_LIGHT_ON } else { BUTTON_LIGHT_OFF });
+        }
+
+        for (line, text) in mackie.screen_lines() {
+            maschine.write_text(line, &text);
+        }
+    }
+
+    /// The knobs that are active, those of the current page unless Volume, Swing or Tempo is lit.
+    /// There can be fewer than 8.
+    pub fn knobs_mut(&mut self) -> &mut [Knob] {
+        match self.knob_function {
+            KnobFunction::Pages => &mut self.knob_pages[self.knob_page].knobs,
+            function => &mut self.knob_functions.get_mut(function).unwrap().knobs,
+        }
+    }
+
+    pub fn switch_knob_page(&mut self, maschine: &mut dyn Maschine, page: usize) {
+        if page >= self.knob_pages.len() {
+            return;
+        }
+
+        if page == self.knob_page && self.knob_function == KnobFunction::Pages {
+            return;
+        }
+
+        self.knob_page = page;
+        self.knob_function = KnobFunction::Pages;
+        self.last_knob = None;
+        self.take_over_parameters();
+        self.show_knob_page(maschine);
+
+        self.send_osc_msg("/maschine/knob_page", osc_args![page as i32]);
+    }
+
+    /// Pressing a lit function button goes back to the knob pages.
+    pub fn toggle_knob_function(&mut self, maschine: &mut dyn Maschine, function: KnobFunction) {
+        self.knob_function = if self.knob_function == function {
+            KnobFunction::Pages
+        } else {
+            function
+        };
+        self.last_knob = None;
+
+        self.take_over_parameters();
+        self.show_knob_page(maschine);
+    }
+
+    /// Lets the knobs that just became active know where their parameters are.
+    fn take_over_parameters(&mut self) {
+        let parameter_values = mem::take(&mut self.parameter_values);
+
+        for knob in self.knobs_mut() {
+            if let Some(&value) = parameter_values.get(&(knob.channel, knob.cc)) {
+                knob.set_parameter(value);
+            }
+        }
+
+        self.parameter_values = parameter_values;
+    }
+
+    pub fn set_tempo(&mut self, maschine: &mut dyn Maschine, bpm: f32) {
+        for msg in self.knob_functions.set_bpm(bpm) {
+            self.send_midi_msg(&msg);
+        }
+
+        maschine.set_seq_speed(bpm_to_seq_speed(self.knob_functions.bpm()));
+    }
+
+    /// The tempo follows the average of the last few taps, a pause starts over.
+    fn tap_tempo(&mut self, maschine: &mut dyn Maschine) {
+        let now = Instant::now();
+
+        if let Some(&last_tap) = self.tempo_taps.last() {
+            if now.duration_since(last_tap) > TAP_TEMPO_TIMEOUT {
+                self.tempo_taps.clear();
+            }
+        }
+
+        self.tempo_taps.push(now);
+        if self.tempo_taps.len() > TAP_TEMPO_TAPS {
+            self.tempo_taps.remove(0);
+        }
+
+        if self.tempo_taps.len() < 2 {
+            return;
+        }
+
+        let beat = now.duration_since(self.tempo_taps[0]).as_secs_f32() / (self.tempo_taps.len() - 1) as f32;
+        self.set_tempo(maschine, 60.0 / beat);
+    }
+
+    fn show_knob_lights(&self, maschine: &mut dyn Maschine) {
+        let brightness = |lit| if lit { BUTTON_LIGHT_ON } else { BUTTON_LIGHT_OFF };
+        let pages = self.knob_function == KnobFunction::Pages;
+
+        maschine.set_button_light(MaschineButton::Pageleft, 0xFFFFFF, brightness(pages && self.knob_page > 0));
+        maschine.set_button_light(
+            MaschineButton::Pageright,
+            0xFFFFFF,
+            brightness(pages && self.knob_page + 1 < self.knob_pages.len()),
+        );
+
+        for &(btn, function) in &[
+            (MaschineButton::Volume, KnobFunction::Volume),
+            (MaschineButton::Swing, KnobFunction::Swing),
+            (MaschineButton::Tempo, KnobFunction::Tempo),
+        ] {
+            maschine.set_button_light(btn, 0xFFFFFF, brightness(self.knob_function == function));
+        }
+    }
+
+    /// The function buttons and Page Left/Right light up for what the knobs are doing, the
+    /// screen shows the page and what its knobs do.
+    pub fn show_knob_page(&self, maschine: &mut dyn Maschine) {
+        self.show_knob_lights(maschine);
+
+        let (page, title) = match self.knob_function {
+            KnobFunction::Pages => {
+                let page = &self.knob_pages[self.knob_page];
+                (page, format!("PAGE {}/{} {}", self.knob_page + 1, self.knob_pages.len(), page.name))
+            }
+            KnobFunction::Volume => (&self.knob_functions.volume, "VOLUME".to_string()),
+            KnobFunction::Swing => (&self.knob_functions.swing, "SWING".to_string()),
+            KnobFunction::Tempo => (&self.knob_functions.tempo, format!("TEMPO {:.0} BPM", self.knob_functions.bpm())),
+        };
+
+        maschine.write_text(0, &title);
+
+        for line in 0..2 {
+            let names: String = page
+                .knobs
+                .iter()
+                .skip(line * 4)
+                .take(4)
+                .map(|knob| match knob.name.as_str() {
+                    "" => format!("{:<10}", format!("CC {}", knob.cc)),
+                    name => format!("{:<10.9}", name),
+                })
+                .collect();
+
+            maschine.write_text(line + 1, &names);
+        }
+    }
+
+    /// Pushing the encoder does what `encoder_push` says, letting go only matters for the CC.
+    fn push_encoder(&mut self, maschine: &mut dyn Maschine, is_down: bool) {
+        self.send_osc_msg("/maschine/encoder_push", osc_args![is_down as i32]);
+
+        match self.encoder_push {
+            EncoderPush::Cc => {
+                let value = if is_down { 127 } else { 0 };
+                self.send_midi_msg(&Message::ControlChange(self.encoder.channel, ENCODER_PUSH_CC, value));
+            }
+
+            EncoderPush::Confirm => {
+                let status = if is_down { 127 } else { 0 };
+                self.send_osc_button_msg(maschine, MaschineButton::Enter, status, is_down, false);
+            }
+
+            EncoderPush::Reset if is_down => self.reset_last_knob(maschine),
+            EncoderPush::Reset => {}
+        }
+    }
+
+    fn reset_last_knob(&mut self, maschine: &mut dyn Maschine) {
+        let last_knob = self.last_knob;
+        let knob = match last_knob.and_then(|knob_idx| self.knobs_mut().get_mut(knob_idx)) {
+            Some(knob) => knob,
+            None => return,
+        };
+
+        let msgs = knob.reset();
+        let (parameter, value) = (knob.parameter(), knob.value());
+
+        for msg in &msgs {
+            self.send_midi_msg(msg);
+        }
+
+        if let Some(parameter) = parameter {
+            self.parameter_values.insert(parameter, value);
+        }
+
+        if self.knob_function == KnobFunction::Tempo {
+            maschine.set_seq_speed(bpm_to_seq_speed(self.knob_functions.bpm()));
+            self.show_knob_page(maschine);
+        }
+    }
+
+    /// Knob movements are recorded while Rec is held or armed with Shift+Rec.
+    fn recording(&self) -> bool {
+        self.rec_held || self.rec_armed
+    }
+
+    fn set_rec_armed(&mut self, maschine: &mut dyn Maschine, armed: bool) {
+        self.rec_armed = armed;
+        if !self.recording() {
+            self.automation.stop_recording();
+        }
+
+        let brightness = if armed { BUTTON_LIGHT_ON } else { BUTTON_LIGHT_OFF };
+        maschine.set_button_light(MaschineButton::Rec, 0xFFFFFF, brightness);
+    }
+
+    /// While Shift is held the buttons that do something else light up, letting go of it
+    /// brings their usual lights back.
+    fn show_shift_lights(&mut self, maschine: &mut dyn Maschine, shift: bool) {
+        for btn in self.mapping.shifted_buttons() {
+            let lit = shift
+                || match self.mapping.button(Layer::Plain, btn) {
+                    Some(mapping) => mapping.action.lights_button() && self.mapping.is_on(Layer::Plain, btn),
+                    None => false,
+                };
+
+            maschine.set_button_light(btn, 0xFFFFFF, if lit { BUTTON_LIGHT_ON } else { BUTTON_LIGHT_OFF });
+        }
+
+        if !shift {
+            self.show_knob_lights(maschine);
+
+            if self.rec_armed {
+                maschine.set_button_light(MaschineButton::Rec, 0xFFFFFF, BUTTON_LIGHT_ON);
+            }
+
+            self.show_mackie(maschine);
+        }
+    }
+
+    /// Plays back the knob automation up to `position` in the sequencer's bar.
+    pub fn play_automation(&mut self, position: Option<f32>) {
+        for (parameter, value, msgs) in self.automation.advance(position) {
+            for msg in &msgs {
+                self.send_midi_msg(msg);
+            }
+
+            self.set_parameter_value(parameter, value);
+        }
+    }
+
+    /// Remembers where a parameter is now and tells the active knobs on it.
+    fn set_parameter_value(&mut self, parameter: (Channel, U7), value: U7) {
+        self.parameter_values.insert(parameter, value);
+
+        for knob in self.knobs_mut() {
+            if (knob.channel, knob.cc) == parameter {
+                knob.set_parameter(value);
+            }
+        }
+    }
+
+    /// Puts the pitch strip on a row of pads, the pads it leaves go back to the pad mode.
+    pub fn set_pitch_strip_row(&mut self, maschine: &mut dyn Maschine, row: Option<usize>) {
+        if let Some(msg) = self.pitch_strip.set_row(row) {
+            self.send_midi_msg(&msg);
+        }
+
+        let (ctx, modes) = self.split_modes();
+        modes.current_mut().render_lights(&ctx, maschine);
+
+        if let Some(row) = self.pitch_strip.row() {
+            for pad_idx in row * 4..row * 4 + 4 {
+                maschine.set_pad_light(pad_idx, self.pad_color(), PAD_RELEASED_BRIGHTNESS);
+            }
+        }
+    }
+
+    fn pitch_strip_pressure(&mut self, maschine: &mut dyn Maschine, pad_idx: usize, column: usize, pressure: f32) {
+        if let Some(msg) = self.pitch_strip.set_pressure(column, pressure) {
+            self.send_midi_msg(&msg);
+        }
+
+        let brightness = if pressure > 0.0 { pressure.sqrt() } else { PAD_RELEASED_BRIGHTNESS };
+        maschine.set_pad_light(pad_idx, self.pad_color(), brightness);
+    }
+
+    /// The knobs of the shift layer, Shift doesn't make them fine. False for the knobs it
+    /// leaves out, they stay fine mode.
+    fn shift_knob_moved(&mut self, knob_idx: usize, delta: i32) -> bool {
+        let knob = match self.mapping.shift_knobs.as_mut().and_then(|page| page.knobs.get_mut(knob_idx)) {
+            Some(knob) => knob,
+            None => return false,
+        };
+
+        let msgs = knob.turn(delta, false);
+        let (value, parameter) = (knob.value(), knob.parameter());
+
+        for msg in &msgs {
+            self.send_midi_msg(msg);
+        }
+
+        if let Some(parameter) = parameter {
+            self.parameter_values.insert(parameter, value);
+        }
+
+        true
+    }
+
+    fn pad_mode_knob_value(&mut self, maschine: &mut dyn Maschine, knob_idx: usize, value: U7) -> bool {
+        let (mut ctx, modes) = self.split_modes();
+        modes.current_mut().knob_value(&mut ctx, maschine, knob_idx, value)
     }
 
     #[allow(dead_code)]
@@ -77,7 +605,7 @@ impl<'a> MHandler<'a> {
 
 
 
-    pub fn recv_osc_msg(&self, maschine: &mut dyn Maschine) {
+    pub fn recv_osc_msg(&mut self, maschine: &mut dyn Maschine) {
         let mut buf = [0u8; 128];
 
         let nbytes = match self.osc_socket.recv_from(&mut buf) {
@@ -99,7 +627,7 @@ impl<'a> MHandler<'a> {
         self.handle_osc_messge(maschine, &msg);
     }
 
-    pub  fn handle_osc_messge(&self, maschine: &mut dyn Maschine, msg: &osc::Message) {
+    pub  fn handle_osc_messge(&mut self, maschine: &mut dyn Maschine, msg: &osc::Message) {
         if msg.path.starts_with("/maschine/button") {
             let btn = match osc_button_to_btn_map(&msg.path[17..]) {
                 Some(btn) => btn,
@@ -127,6 +655,124 @@ impl<'a> MHandler<'a> {
 
                 _ => return,
             };
+        } else if msg.path.starts_with("/maschine/knob_page") {
+            if let [osc::Argument::i(page @ 0..)] = msg.arguments[..] {
+                self.switch_knob_page(maschine, page as usize);
+            }
+        } else if msg.path.starts_with("/maschine/knob_encoding") {
+            if let [osc::Argument::i(knob @ 0..=7), osc::Argument::s(name)] = msg.arguments[..] {
+                if let Some(encoding) = KnobEncoding::from_name(name) {
+                    if let Some(knob) = self.knobs_mut().get_mut(knob as usize) {
+                        if encoding.fits(knob.cc) {
+                            knob.encoding = encoding;
+                        }
+                    }
+                }
+            }
+        } else if msg.path.starts_with("/maschine/encoder_encoding") {
+            if let [osc::Argument::s(name)] = msg.arguments[..] {
+                if let Some(encoding) = KnobEncoding::from_name(name) {
+                    if encoding.fits(self.encoder.cc) {
+                        self.encoder.encoding = encoding;
+                    }
+                }
+            }
+        } else if msg.path.starts_with("/maschine/encoder_push") {
+            if let [osc::Argument::s(name)] = msg.arguments[..] {
+                if let Some(push) = EncoderPush::from_name(name) {
+                    self.encoder_push = push;
+                }
+            }
+        } else if msg.path.starts_with("/maschine/knob_takeover") {
+            if let [osc::Argument::i(knob @ 0..=7), osc::Argument::s(name)] = msg.arguments[..] {
+                if let Some(takeover) = Takeover::from_name(name) {
+                    if let Some(knob) = self.knobs_mut().get_mut(knob as usize) {
+                        knob.takeover = takeover;
+                    }
+                }
+            }
+        } else if msg.path.starts_with("/maschine/knob_acceleration") {
+            if let [osc::Argument::i(knob @ 0..=7), ref curve @ ..] = msg.arguments[..] {
+                if let Some(acceleration) = osc_acceleration(curve) {
+                    if let Some(knob) = self.knobs_mut().get_mut(knob as usize) {
+                        knob.acceleration = acceleration;
+                    }
+                }
+            }
+        } else if msg.path.starts_with("/maschine/encoder_acceleration") {
+            if let Some(acceleration) = osc_acceleration(&msg.arguments) {
+                self.encoder.acceleration = acceleration;
+            }
+        } else if msg.path.starts_with("/maschine/pitch_strip") {
+            match msg.arguments[..] {
+                [osc::Argument::i(row @ 0..=3)] => self.set_pitch_strip_row(maschine, Some(row as usize)),
+                [osc::Argument::s("off")] => self.set_pitch_strip_row(maschine, None),
+                _ => {}
+            }
+        } else if msg.path.starts_with("/maschine/pad_cc") {
+            if let [osc::Argument::i(pad @ 0..=15), osc::Argument::i(cc @ 0..=127)] = msg.arguments[..] {
+                self.modes.faders.controls[pad as usize] = PadControl::Cc(cc as U7);
+            }
+        } else if msg.path.starts_with("/maschine/pad_nrpn") {
+            if let [osc::Argument::i(pad @ 0..=15), osc::Argument::i(nrpn @ 0..=16383)] = msg.arguments[..] {
+                self.modes.faders.controls[pad as usize] = PadControl::Nrpn(nrpn as U14);
+            }
+        } else if msg.path.starts_with("/maschine/pad_hold") {
+            if let [osc::Argument::i(hold)] = msg.arguments[..] {
+                self.modes.faders.release = if hold != 0 { PadRelease::Hold } else { PadRelease::SnapBack };
+            }
+        } else if msg.path.starts_with("/maschine/pad_velocity_zones") {
+            // pad, then a (min velocity, note, channel) triple per zone, note -1 keeps the pad's note
+            if let Some(&osc::Argument::i(pad @ 0..=15)) = msg.arguments.first() {
+                let zones = msg.arguments[1..]
+                    .chunks(3)
+                    .filter_map(|zone| match *zone {
+                        [osc::Argument::i(min_velocity @ 0..=127), osc::Argument::i(note @ -1..=127), osc::Argument::i(channel @ 1..=16)] => {
+                            Some(VelocityZone {
+                                min_velocity: min_velocity as U7,
+                                note: if note < 0 { None } else { Some(note as U7) },
+                                channel: MIDI_CHANNELS[channel as usize - 1],
+                            })
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+
+                match check_velocity_zones(&zones) {
+                    Ok(()) => self.modes.notes.set_velocity_zones(pad as usize, zones),
+                    Err(err) => println!(" :: {} for pad {}", err, pad),
+                }
+            }
+        } else if msg.path.starts_with("/maschine/pad_mode_cycle") {
+            let cycle = msg
+                .arguments
+                .iter()
+                .filter_map(|arg| match *arg {
+                    osc::Argument::s(name) => PadMode::from_name(name),
+                    _ => None,
+                })
+                .collect();
+
+            self.modes.set_cycle(cycle);
+        } else if msg.path.starts_with("/maschine/pad_mode") {
+            let mode = match msg.arguments[..] {
+                [osc::Argument::s(name)] => PadMode::from_name(name),
+                [osc::Argument::i(idx)] => PadMode::ALL.get(idx as usize).cloned(),
+                _ => None,
+            };
+
+            if let Some(mode) = mode {
+                self.switch_pad_mode(maschine, mode);
+            }
+        } else if msg.path.starts_with("/maschine/program_bank_select") {
+            if let [osc::Argument::i(mode)] = msg.arguments[..] {
+                self.modes.program.bank_select = match mode {
+                    1 => BankSelect::Msb,
+                    2 => BankSelect::Lsb,
+                    3 => BankSelect::MsbLsb,
+                    _ => BankSelect::Off,
+                };
+            }
         } else if msg.path.starts_with("/maschine/pad") {
             match msg.arguments.len() {
                 3 => {
@@ -179,521 +825,310 @@ impl<'a> MHandler<'a> {
         btn: MaschineButton,
         status: usize,
         is_down: bool,
+        gestured: bool,
     ) {
         let button = btn_to_osc_button_map(btn);
-        let controlbase = 15;
         let modpress = maschine.get_mod();
+        match btn {
+            MaschineButton::Padmode => self.pad_mode_held = is_down,
+            MaschineButton::Encoder => self.push_encoder(maschine, is_down),
+            MaschineButton::Erase => self.erase_held = is_down,
+            MaschineButton::Rec => {
+                self.rec_held = is_down;
+                if !self.recording() {
+                    self.automation.stop_recording();
+                }
+            }
+            _ => {}
+        }
         if button.contains("shift") {
-            if status > 0 {
-                maschine.set_mod(1);
+            maschine.set_mod(is_down as usize);
+            self.show_shift_lights(maschine, is_down);
+        }
+
+        // a press that started a mapped gesture doesn't do its plain action as well
+        if is_down && status <= 250 && !gestured {
+            if self.pad_mode_button_down(maschine, btn) {
+                self.mode_buttons.insert(btn);
             } else {
-                maschine.set_mod(0);
+                self.press_button(maschine, btn, modpress == 1);
             }
+        } else if !is_down && !self.mode_buttons.remove(&btn) {
+            self.release_button(maschine, btn, modpress == 1);
         }
-        if button.contains("C") {
-            let idx = 1;
-            if button == "C8" {
-                maschine.set_roller_state(status, idx);
-                //println!("3={}", status);
-            };
-            if button == "C7" {
-                maschine.set_roller_state(status, idx);
-                //println!("2={}", status);
-            };
-        };
-        if button.contains("E") {
-            let idx = 2;
-            if button == "E8" {
-                maschine.set_roller_state(status, idx);
-                //println!("3={}", status);
-            };
-        };
-        if button.contains("G") {
-            let idx = 3;
-            if button == "G8" {
-                maschine.set_roller_state(status, idx);
-                //println!("3={}", status);
-            };
-        };
-        if button.contains("I") {
-            let idx = 4;
-            if button == "I8" {
-                maschine.set_roller_state(status, idx);
-                //println!("3={}", status);
-            };
-        };
-        if button.contains("K") {
-            let idx = 5;
-            if button == "K8" {
-                maschine.set_roller_state(status, idx);
-                //println!("3={}", status);
-            };
-        };
-        if button.contains("M") {
-            let idx = 6;
-            if button == "M8" {
-                maschine.set_roller_state(status, idx);
-                //println!("3={}", status);
-            };
-        };
-        if button.contains("O") {
-            let idx = 7;
-            if button == "O8" {
-                maschine.set_roller_state(status, idx);
-                //println!("3={}", status);
-            };
+        self.send_osc_msg(&format!("/{}", button), osc_args![status as f32]);
+    }   
+
+    fn press_button(&mut self, maschine: &mut dyn Maschine, btn: MaschineButton, shift: bool) {
+        if !self.mackie_button(maschine, btn, true) {
+            self.button_action(maschine, btn, true, shift);
+        }
+    }
+
+    fn release_button(&mut self, maschine: &mut dyn Maschine, btn: MaschineButton, shift: bool) {
+        if !self.mackie_button(maschine, btn, false) {
+            self.button_action(maschine, btn, false, shift);
+        }
+    }
+
+    /// Rec or Erase did their part in automation, holding them on isn't a long press.
+    fn used_for_automation(&mut self, btn: MaschineButton) {
+        self.gestures.consume(btn);
+    }
+
+    /// Turns buttons on and off the way the mapping says for a press or release of `btn`, in
+    /// the shift layer if `shift`.
+    fn button_action(&mut self, maschine: &mut dyn Maschine, btn: MaschineButton, is_down: bool, shift: bool) {
+        for (layer, btn, on) in self.mapping.transitions(btn, is_down, shift) {
+            self.switch_button(maschine, layer, btn, on);
+        }
+    }
+
+    /// Does what a mapped button does when it turns on or off, and lights it while it's on.
+    fn switch_button(&mut self, maschine: &mut dyn Maschine, layer: Layer, btn: MaschineButton, on: bool) {
+        let action = match self.mapping.button(layer, btn) {
+            Some(mapping) => mapping.action.clone(),
+            None => return,
         };
-        if button.contains("A8") {
-            let msg = Message::RPN7(Ch1, controlbase, status as u8 * 8);
-            self.seq_port.send_message(&msg).unwrap();
-            self.seq_handle.drain_output();
-        }
-
-        if is_down == true && status <= 250 {
-            match button {
-                "play" => {
-                    if status > 0 && maschine.get_padmode() != 2 {
-                        let msg = Message::RPN7(Ch1, 1, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    } else if maschine.get_padmode() == 2 {
-                        maschine.set_playing(1);
-                        println!("playing notes");
-                    };
-                }
 
-                "stop" => {
-                    if status > 0 && maschine.get_padmode() != 2 {
-                        let msg = Message::RPN7(Ch1, 2, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    } else {
-                        maschine.set_playing(0);
-                        println!("stop");
-                        //let msg2 = Message::AllNotesOff(Ch2);
-                        //self.seq_port.send_message(&msg2).unwrap();
-                        //self.seq_handle.drain_output();
-                    }
-                }
-                "rec" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 3, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "grid" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 4, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "step_left" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 5, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "step_right" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 6, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "restart" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 7, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "browse" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 8, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "sampling" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 9, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "note_repeat" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 10, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "control" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 11, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "nav" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 12, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "nav_left" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 13, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "nav_right" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 14, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "main" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 24, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "scene" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 25, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "pattern" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 26, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "pad_mode" => {
-                    if modpress == 1 {
-                        maschine.set_padmode(1);
-                    } else {
-                        if status > 0 {
-                            let msg = Message::RPN7(Ch1, 27, status as u8);
-                            self.seq_port.send_message(&msg).unwrap();
-                            self.seq_handle.drain_output();
-                        }
-                    }
-                }
-                "view" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 28, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "duplicate" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 29, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "select" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 30, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "solo" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 31, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "step" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 32, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "mute" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 33, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "navigate" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 34, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "tempo" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 35, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "enter" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 36, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "auto" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 37, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "all" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 38, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "f1" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 39, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "f2" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 40, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "f3" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 41, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "f4" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 42, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "f5" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 43, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "f6" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 44, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "f7" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 45, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "f8" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 46, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "page_right" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 47, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
-                }
-                "page_left" => {
-                    if status > 0 {
-                        let msg = Message::RPN7(Ch1, 48, status as u8);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    }
+        if action.lights_button() {
+            let brightness = if on { BUTTON_LIGHT_ON } else { BUTTON_LIGHT_OFF };
+            maschine.set_button_light(btn, 0xFFFFFF, brightness);
+        }
+
+        self.run_action(maschine, &action, on);
+    }
+
+    /// Runs the mapped actions of gestures that start or end, returns whether any was mapped.
+    fn run_gestures(&mut self, maschine: &mut dyn Maschine, gestures: Vec<Gesture>, on: bool) -> bool {
+        let mut mapped = false;
+
+        for gesture in gestures {
+            if let Some(action) = self.mapping.gesture(gesture).cloned() {
+                // the held button was part of the combo, it doesn't go on to a gesture of its own
+                if let (Gesture::Combo(held, _), true) = (gesture, on) {
+                    self.gestures.consume(held);
                 }
 
-                "B6" => {
-                    let idx = 1;
-                    let state = maschine.get_roller_state(idx);
-                    let status = status / 4 + state * 64;
-                    if modpress != 1 {
-                        let msg = Message::RPN14(Ch1, controlbase + 1, status as u16 / 2);
-                        self.seq_port.send_message(&msg).unwrap();
-                        self.seq_handle.drain_output();
-                    } else {
-                        maschine.set_seq_speed(status);
+                self.run_action(maschine, &action, on);
+                mapped = true;
+            }
+        }
+
+        mapped
+    }
+
+    /// Starts the long presses that are due.
+    pub fn poll_gestures(&mut self, maschine: &mut dyn Maschine) {
+        let gestures = self.gestures.poll(Instant::now());
+        self.run_gestures(maschine, gestures, true);
+    }
+
+    /// Plays the macro steps that are due. Every action is played as a press and a release,
+    /// beats are at the MIDI clock's tempo when it's coming in and at our own otherwise.
+    pub fn poll_macros(&mut self, maschine: &mut dyn Maschine) {
+        let now = Instant::now();
+        let beat = match self.beat.clock_beat(now) {
+            Some(beat) => beat,
+            None => Duration::from_secs_f32(60.0 / self.knob_functions.bpm()),
+        };
+
+        for action in self.macros.poll(now, beat) {
+            self.run_action(maschine, &action, true);
+            self.run_action(maschine, &action, false);
+        }
+    }
+
+    /// Sends what an action sends when its button or pad turns on or off.
+    fn run_action(&mut self, maschine: &mut dyn Maschine, action: &ButtonAction, on: bool) {
+        for msg in action.msgs(on) {
+            self.send_midi_msg(&msg);
+        }
+
+        match *action {
+            ButtonAction::Osc { ref path } => self.send_osc_msg(path, osc_args![on as i32]),
+
+            ButtonAction::NoteBase { note } if on => maschine.set_midi_note_base(note),
+
+            ButtonAction::Action { action } if on => match action {
+                InternalAction::Volume => self.toggle_knob_function(maschine, KnobFunction::Volume),
+                InternalAction::Swing => self.toggle_knob_function(maschine, KnobFunction::Swing),
+                InternalAction::Tempo => self.toggle_knob_function(maschine, KnobFunction::Tempo),
+                InternalAction::TapTempo => self.tap_tempo(maschine),
+                InternalAction::PageLeft => {
+                    if self.knob_page > 0 {
+                        let page = self.knob_page - 1;
+                        self.switch_knob_page(maschine, page);
                     }
                 }
-                "D6" => {
-                    let idx = 2;
-                    let state = maschine.get_roller_state(idx);
-                    let status = status / 4 + state * 64;
-                    let msg = Message::RPN14(Ch1, controlbase + 2, status as u16 / 2);
-                    self.seq_port.send_message(&msg).unwrap();
-                    self.seq_handle.drain_output();
-                }
-                "FF6" => {
-                    let idx = 3;
-                    let state = maschine.get_roller_state(idx);
-                    let status = status / 4 + state * 64;
-                    let msg = Message::RPN14(Ch1, controlbase + 3, status as u16 / 2);
-                    self.seq_port.send_message(&msg).unwrap();
-                    self.seq_handle.drain_output();
+                InternalAction::PageRight => {
+                    let page = self.knob_page + 1;
+                    self.switch_knob_page(maschine, page);
                 }
-                "H6" => {
-                    let idx = 4;
-                    let state = maschine.get_roller_state(idx);
-                    let status = status / 4 + state * 64;
-                    let msg = Message::RPN14(Ch1, controlbase + 4, status as u16 / 2);
-                    self.seq_port.send_message(&msg).unwrap();
-                    self.seq_handle.drain_output();
+                InternalAction::NextPadMode => {
+                    let mode = self.modes.next_in_cycle();
+                    self.switch_pad_mode(maschine, mode);
                 }
-                "J6" => {
-                    let idx = 5;
-                    let state = maschine.get_roller_state(idx);
-                    let status = status / 4 + state * 64;
-                    let msg = Message::RPN14(Ch1, controlbase + 5, status as u16 / 2);
-                    self.seq_port.send_message(&msg).unwrap();
-                    self.seq_handle.drain_output();
-                }
-                "L6" => {
-                    let idx = 6;
-                    let state = maschine.get_roller_state(idx);
-                    let status = status / 4 + state * 64;
-                    let msg = Message::RPN14(Ch1, controlbase + 6, status as u16 / 2);
-                    self.seq_port.send_message(&msg).unwrap();
-                    self.seq_handle.drain_output();
-                }
-                "N6" => {
-                    let idx = 7;
-                    let state = maschine.get_roller_state(idx);
-                    let status = status / 4 + state * 64;
-                    let msg = Message::RPN14(Ch1, controlbase + 7, status as u16 / 2);
-                    self.seq_port.send_message(&msg).unwrap();
-                    self.seq_handle.drain_output();
-                }
-                "P6" => {
-                    let msg = Message::RPN14(Ch1, controlbase + 8, status as u16 / 2);
-                    self.seq_port.send_message(&msg).unwrap();
-                    self.seq_handle.drain_output();
+                InternalAction::RecArm => {
+                    let armed = !self.rec_armed;
+                    self.set_rec_armed(maschine, armed);
                 }
+            },
 
-                "group_a" => {
-                    maschine.set_midi_note_base(24);
-                }
-                "group_b" => {
-                    maschine.set_midi_note_base(36);
-                }
-                "group_c" => {
-                    maschine.set_midi_note_base(48);
-                }
-                "group_d" => {
-                    maschine.set_midi_note_base(60);
-                }
-                "group_e" => {
-                    maschine.set_midi_note_base(72);
+            ButtonAction::Macro { ref name } if on => {
+                if let Some(steps) = self.mapping.macro_steps(name) {
+                    self.macros.start(steps.to_vec(), Instant::now());
                 }
-                "group_f" => {
-                    maschine.set_midi_note_base(84);
-                }
-                "group_g" => {
-                    maschine.set_midi_note_base(96);
-                }
-                "group_h" => {
-                    maschine.set_midi_note_base(108);
-                }
-                _ => {}
             }
+
+            _ => {}
         }
-        self.send_osc_msg(&*format!("/{}", button), osc_args![status as f32]);
-    }   
+    }
 
     pub fn send_osc_encoder_msg(&self, delta: i32) {
         self.send_osc_msg("/maschine/encoder", osc_args![delta]);
     }
+
+    pub fn send_osc_knob_msg(&self, knob_idx: usize, delta: i32) {
+        self.send_osc_msg("/maschine/knob", osc_args![knob_idx as i32, delta]);
+    }
+}
+
+/// `s "off"`, or a curve with its amount: `sf "linear" 1.0`, `sf "exponential" 2.0`.
+fn osc_acceleration(arguments: &[osc::Argument]) -> Option<Acceleration> {
+    match *arguments {
+        [osc::Argument::s("off")] => Some(Acceleration::Off),
+        [osc::Argument::s("linear"), osc::Argument::f(amount)] => Some(Acceleration::Linear(amount)),
+        [osc::Argument::s("exponential"), osc::Argument::f(power)] => Some(Acceleration::Exponential(power)),
+        _ => None,
+    }
 }
 
 impl<'a> MaschineHandler for MHandler<'a> {
-  fn pad_pressed(&mut self, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {
-        let midi_note = maschine.get_midi_note_base() + PAD_NOTE_MAP[pad_idx];
-        let msg = Message::NoteOn(Ch1, midi_note, self.pressure_to_vel(pressure));
-        if maschine.get_padmode() == 2 {
-            if maschine.get_mod() != 1 {
-                if maschine.note_check(pad_idx) == 0 {
-                    maschine.note_state(pad_idx, 1);
-                    maschine.set_pad_light(pad_idx, self.pad_color(), pressure.sqrt());
-                } else {
-                    maschine.note_state(pad_idx, 0);
-                    maschine.set_pad_light(pad_idx, self.pad_color(), PAD_RELEASED_BRIGHTNESS);
-                };
-            } else {
-                maschine.note_save(pad_idx, midi_note, self.pressure_to_vel(pressure));
-            };
-        } else {
-            self.seq_port.send_message(&msg).unwrap();
-            self.seq_handle.drain_output();
-            maschine.set_pad_light(pad_idx, self.pad_color(), pressure.sqrt());
-        };
+    fn pad_pressed(&mut self, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {
+        if let Some(column) = self.pitch_strip.column(pad_idx) {
+            return self.pitch_strip_pressure(maschine, pad_idx, column, pressure);
+        }
+
+        let gestures = self.gestures.press(Control::Pad(pad_idx), Instant::now());
+        if self.run_gestures(maschine, gestures, true) {
+            self.mapped_pads[pad_idx] = true;
+            return maschine.set_pad_light(pad_idx, self.pad_color(), 1.0);
+        }
+
+        if maschine.get_mod() == 1 {
+            if let Some(action) = self.mapping.shift_pad(pad_idx).cloned() {
+                self.mapped_pads[pad_idx] = true;
+                self.run_action(maschine, &action, true);
+                return maschine.set_pad_light(pad_idx, self.pad_color(), 1.0);
+            }
+        }
+
+        let (mut ctx, modes) = self.split_modes();
+        modes.current_mut().pad_pressed(&mut ctx, maschine, pad_idx, pressure);
     }
 
     fn pad_aftertouch(&mut self, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {
-        match self.pressure_shape {
-            PressureShape::Constant(_) => return,
-            _ => {}
+        if let Some(column) = self.pitch_strip.column(pad_idx) {
+            return self.pitch_strip_pressure(maschine, pad_idx, column, pressure);
         }
 
-        if !self.send_aftertouch {
+        if self.mapped_pads[pad_idx] {
             return;
         }
 
-        let midi_note = maschine.get_midi_note_base() + PAD_NOTE_MAP[pad_idx];
-        let msg = Message::PolyphonicPressure(Ch1, midi_note, self.pressure_to_vel(pressure));
-
-        self.seq_port.send_message(&msg).unwrap();
-        self.seq_handle.drain_output();
-
-        maschine.set_pad_light(pad_idx, self.pad_color(), pressure.sqrt());
+        let (mut ctx, modes) = self.split_modes();
+        modes.current_mut().pad_aftertouch(&mut ctx, maschine, pad_idx, pressure);
     }
 
     fn pad_released(&mut self, maschine: &mut dyn Maschine, pad_idx: usize) {
-        if maschine.get_padmode() != 2 {
-            let midi_note = maschine.get_midi_note_base() + PAD_NOTE_MAP[pad_idx];
-            let msg = Message::NoteOff(Ch1, midi_note, 0);
-            self.seq_port.send_message(&msg).unwrap();
-            self.seq_handle.drain_output();
-            maschine.set_pad_light(pad_idx, self.pad_color(), PAD_RELEASED_BRIGHTNESS);
-        };
+        if let Some(column) = self.pitch_strip.column(pad_idx) {
+            return self.pitch_strip_pressure(maschine, pad_idx, column, 0.0);
+        }
+
+        let gestures = self.gestures.release(Control::Pad(pad_idx));
+        if mem::replace(&mut self.mapped_pads[pad_idx], false) {
+            if !self.run_gestures(maschine, gestures, false) {
+                if let Some(action) = self.mapping.shift_pad(pad_idx).cloned() {
+                    self.run_action(maschine, &action, false);
+                }
+            }
+            return maschine.set_pad_light(pad_idx, self.pad_color(), PAD_RELEASED_BRIGHTNESS);
+        }
+
+        let (mut ctx, modes) = self.split_modes();
+        modes.current_mut().pad_released(&mut ctx, maschine, pad_idx);
+
+        if let Some(lit) = self.lit.get(&Control::Pad(pad_idx)) {
+            show_lit(maschine, Control::Pad(pad_idx), lit.at(self.beat.phase(Instant::now())));
+        }
     }
 
-    fn encoder_step(&mut self, _: &mut dyn Maschine, _: usize, delta: i32) {
+    fn encoder_step(&mut self, maschine: &mut dyn Maschine, _: usize, delta: i32) {
         self.send_osc_encoder_msg(delta);
+
+        let (mut ctx, modes) = self.split_modes();
+        if modes.current_mut().encoder_step(&mut ctx, maschine, delta) {
+            return;
+        }
+
+        for msg in self.encoder.turn(delta, maschine.get_mod() == 1) {
+            self.send_midi_msg(&msg);
+        }
+    }
+
+    fn knob_moved(&mut self, maschine: &mut dyn Maschine, knob_idx: usize, delta: i32) {
+        self.send_osc_knob_msg(knob_idx, delta);
+
+        let shift = maschine.get_mod() == 1;
+        let erase = self.erase_held;
+        self.last_knob = Some(knob_idx);
+
+        if shift && self.shift_knob_moved(knob_idx, delta) {
+            return;
+        }
+
+        if self.knob_function == KnobFunction::Pages {
+            if let Some(ref mut mackie) = self.mackie {
+                return mackie.turn_vpot(knob_idx, delta, shift);
+            }
+        }
+
+        let knob = match self.knobs_mut().get_mut(knob_idx) {
+            Some(knob) => knob,
+            None => return,
+        };
+
+        if erase {
+            let parameter = (knob.channel, knob.cc);
+            self.automation.clear(parameter);
+            return self.used_for_automation(MaschineButton::Erase);
+        }
+
+        let msgs = knob.turn(delta, shift);
+        let (position, value) = (knob.position(), knob.value());
+        let parameter = knob.parameter();
+
+        if self.knob_function == KnobFunction::Tempo {
+            maschine.set_seq_speed(bpm_to_seq_speed(self.knob_functions.bpm()));
+        }
+
+        if knob_idx == 0 && shift && self.knob_function == KnobFunction::Pages {
+            maschine.set_seq_speed((position * 250.0) as usize);
+        } else if !msgs.is_empty() && !self.pad_mode_knob_value(maschine, knob_idx, value) {
+            for msg in &msgs {
+                self.send_midi_msg(msg);
+            }
+
+            if let Some(parameter) = parameter {
+                self.parameter_values.insert(parameter, value);
+
+                if self.recording() {
+                    self.automation.record(parameter, value, msgs);
+                    self.used_for_automation(MaschineButton::Rec);
+                }
+            }
+        }
     }
 
     fn button_down(
@@ -704,7 +1139,9 @@ impl<'a> MaschineHandler for MHandler<'a> {
         is_down: bool,
     ) {
         //println!("{}", byte as usize);
-        self.send_osc_button_msg(maschine, btn, byte as usize, is_down);
+        let gestures = self.gestures.press(Control::Button(btn), Instant::now());
+        let gestured = self.run_gestures(maschine, gestures, true);
+        self.send_osc_button_msg(maschine, btn, byte as usize, is_down, gestured);
     }
 
     fn button_up(
@@ -714,16 +1151,31 @@ impl<'a> MaschineHandler for MHandler<'a> {
         byte: u8,
         is_down: bool,
     ) {
-        self.send_osc_button_msg(maschine, btn, byte as usize, is_down);
+        let gestures = self.gestures.release(Control::Button(btn));
+        self.run_gestures(maschine, gestures, false);
+        self.send_osc_button_msg(maschine, btn, byte as usize, is_down, false);
+    }
+}
+
+/// Feedback brightness goes from 0 to 1, button lights take it up to BUTTON_LIGHT_ON.
+fn show_lit(maschine: &mut dyn Maschine, control: Control, (color, brightness): (u32, f32)) {
+    match control {
+        Control::Pad(pad_idx) => maschine.set_pad_light(pad_idx, color, brightness),
+        Control::Button(btn) => maschine.set_button_light(btn, color, brightness * BUTTON_LIGHT_ON),
     }
 }
 
 pub fn ev_loop(device: &mut dyn Maschine, mhandler: &mut MHandler) {
-    let mut fds = [
+    let mut fds = vec![
         PollFd::new(device.get_fd(), POLLIN, EventFlags::empty()),
         PollFd::new(mhandler.osc_socket.as_raw_fd(), POLLIN, EventFlags::empty()),
     ];
 
+    // everything after the device and OSC is the sequencer's
+    for fd in mhandler.seq_handle.poll_fds() {
+        fds.push(PollFd::new(fd, POLLIN, EventFlags::empty()));
+    }
+
     let mut now = SystemTime::now();
     let mut now2 = SystemTime::now();
     let timer_interval = Duration::from_millis(16);
@@ -742,7 +1194,15 @@ pub fn ev_loop(device: &mut dyn Maschine, mhandler: &mut MHandler) {
             mhandler.recv_osc_msg(device);
         }
 
+        if fds[2..].iter().any(|fd| fd.revents().unwrap().contains(POLLIN)) {
+            mhandler.recv_midi_msgs(device);
+        }
+
+        mhandler.poll_gestures(device);
+        mhandler.poll_macros(device);
+
         if now.elapsed().unwrap() >= timer_interval {
+            mhandler.animate_lights(device);
             device.write_lights();
             now = SystemTime::now();
         }
@@ -752,14 +1212,12 @@ pub fn ev_loop(device: &mut dyn Maschine, mhandler: &mut MHandler) {
             if device.note_check(step) == 1 && now2.elapsed().unwrap() >= timer_interval2 && check == 0
             {
                 let msg = device.load_notes(step, 1);
-                mhandler.seq_port.send_message(&msg).unwrap();
-                mhandler.seq_handle.drain_output();
+                mhandler.send_midi_msg(&msg);
                 check = 1;
             };
             if now2.elapsed().unwrap() >= timer_interval2 * 2 && device.note_check(step) == 1 {
                 let msg = device.load_notes(step, 0);
-                mhandler.seq_port.send_message(&msg).unwrap();
-                mhandler.seq_handle.drain_output();
+                mhandler.send_midi_msg(&msg);
                 now2 = SystemTime::now();
                 step += 1;
                 check = 0;
@@ -771,10 +1229,13 @@ pub fn ev_loop(device: &mut dyn Maschine, mhandler: &mut MHandler) {
             if step >= 16 {
                 step = 0;
             };
+
+            let step_fraction = now2.elapsed().unwrap().as_secs_f32() / (timer_interval2 * 2).as_secs_f32();
+            mhandler.play_automation(Some((step as f32 + step_fraction.min(1.0)) / 16.0));
         } else if active == true {
             let msg = device.load_notes(step, 0);
-            mhandler.seq_port.send_message(&msg).unwrap();
-            mhandler.seq_handle.drain_output();
+            mhandler.send_midi_msg(&msg);
+            mhandler.play_automation(None);
             active = false;
         }
     }
@@ -0,0 +1,640 @@
+use std::time::Instant;
+
+use midi::{Channel, Message, U7};
+use midi::Channel::Ch1;
+
+use base::KNOB_STEPS_PER_TURN;
+use utils::{
+    DEFAULT_BPM, GROUP_NAMES, MIDI_CHANNELS, SWING_CC, TEMPO_CC, TEMPO_MAX_BPM, TEMPO_MIN_BPM, VOLUME_CC,
+};
+
+/// How a knob's movement goes out as CC. The relative encodings send how many ticks the knob
+/// moved since the last message, at most 63 either way.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KnobEncoding {
+    Absolute,
+    /// MSB on the knob's CC, LSB 32 above it.
+    Absolute14,
+    /// 1..63 up, 127..65 down.
+    TwosComplement,
+    /// 65..127 up, 63..1 down.
+    BinaryOffset,
+    /// 1..63 up, 65..127 down.
+    SignMagnitude,
+    /// 14-bit Pitch Bend on the knob's channel, the CC isn't used.
+    PitchBend,
+}
+
+impl KnobEncoding {
+    pub const ALL: [KnobEncoding; 6] = [
+        KnobEncoding::Absolute,
+        KnobEncoding::Absolute14,
+        KnobEncoding::TwosComplement,
+        KnobEncoding::BinaryOffset,
+        KnobEncoding::SignMagnitude,
+        KnobEncoding::PitchBend,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KnobEncoding::Absolute => "absolute",
+            KnobEncoding::Absolute14 => "absolute14",
+            KnobEncoding::TwosComplement => "twos_complement",
+            KnobEncoding::BinaryOffset => "binary_offset",
+            KnobEncoding::SignMagnitude => "sign_magnitude",
+            KnobEncoding::PitchBend => "pitch_bend",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<KnobEncoding> {
+        KnobEncoding::ALL.iter().cloned().find(|encoding| encoding.name() == name)
+    }
+
+    /// Whether a knob on `cc` can send this encoding, 14-bit values need the LSB's CC too.
+    pub fn fits(self, cc: U7) -> bool {
+        self != KnobEncoding::Absolute14 || cc < 32
+    }
+
+    fn relative_value(self, ticks: i32) -> U7 {
+        let ticks = ticks.clamp(-63, 63);
+
+        (match self {
+            KnobEncoding::TwosComplement => ticks & 0x7F,
+            KnobEncoding::BinaryOffset => 64 + ticks,
+            KnobEncoding::SignMagnitude if ticks < 0 => 64 | -ticks,
+            _ => ticks,
+        }) as U7
+    }
+}
+
+/// How much faster a knob moves the faster it's turned, by turns per second.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Acceleration {
+    Off,
+    Linear(f32),
+    Exponential(f32),
+}
+
+impl Acceleration {
+    fn factor(self, turns_per_second: f32) -> f32 {
+        match self {
+            Acceleration::Off => 1.0,
+            Acceleration::Linear(amount) => 1.0 + amount * turns_per_second,
+            Acceleration::Exponential(power) => (1.0 + turns_per_second).powf(power),
+        }
+    }
+}
+
+/// What an absolute knob does when it doesn't match the value the parameter is at, say after
+/// another page sent a different value on the same CC.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Takeover {
+    /// The parameter jumps to the knob.
+    Off,
+    /// Nothing is sent until the knob passes the parameter's value.
+    Pickup,
+    /// The parameter moves from where it is towards the end the knob is turned to, so both
+    /// arrive there together.
+    Scale,
+}
+
+impl Takeover {
+    pub fn from_name(name: &str) -> Option<Takeover> {
+        match name {
+            "off" => Some(Takeover::Off),
+            "pickup" => Some(Takeover::Pickup),
+            "scale" => Some(Takeover::Scale),
+            _ => None,
+        }
+    }
+}
+
+/// What pushing the encoder does.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncoderPush {
+    /// 127 on ENCODER_PUSH_CC while it's down, 0 when it's let go.
+    Cc,
+    /// The same as pressing Enter.
+    Confirm,
+    /// The knob turned last goes back to where it started.
+    Reset,
+}
+
+impl EncoderPush {
+    pub fn from_name(name: &str) -> Option<EncoderPush> {
+        match name {
+            "cc" => Some(EncoderPush::Cc),
+            "confirm" => Some(EncoderPush::Confirm),
+            "reset" => Some(EncoderPush::Reset),
+            _ => None,
+        }
+    }
+}
+
+/// Shift slows every knob down this much.
+pub const FINE_DIVISOR: f32 = 8.0;
+
+// relative knobs send this many ticks over a turn unless they're given their own step size
+const RELATIVE_TICKS_PER_TURN: f32 = 128.0;
+
+/// A knob, or the encoder, and the CC it sends. The position goes from 0 to 1 over a single
+/// turn and stops at either end instead of wrapping around. Absolute values go from `min` to
+/// `max` over that turn. Relative encodings send `ticks_per_step` ticks for every step instead.
+#[derive(Clone)]
+pub struct Knob {
+    pub name: String,
+    pub channel: Channel,
+    pub cc: U7,
+    pub min: U7,
+    pub max: U7,
+    pub encoding: KnobEncoding,
+    pub acceleration: Acceleration,
+    pub takeover: Takeover,
+    pub ticks_per_step: f32,
+
+    steps_per_turn: f32,
+    position: f32,
+    default_position: f32,
+    last_move: Option<Instant>,
+
+    // where the parameter is, as a knob position, while the knob hasn't caught up with it
+    parameter: Option<f32>,
+
+    // movement that didn't add up to a whole relative tick yet
+    residue: f32,
+}
+
+impl Knob {
+    pub fn new(cc: U7, steps_per_turn: i32) -> Self {
+        Knob {
+            name: String::new(),
+            channel: Ch1,
+            cc,
+            min: 0,
+            max: 127,
+            encoding: KnobEncoding::Absolute,
+            acceleration: Acceleration::Off,
+            takeover: Takeover::Off,
+            ticks_per_step: RELATIVE_TICKS_PER_TURN / steps_per_turn as f32,
+
+            steps_per_turn: steps_per_turn as f32,
+            position: 0.0,
+            default_position: 0.0,
+            last_move: None,
+            parameter: None,
+            residue: 0.0,
+        }
+    }
+
+    /// Moves the knob by `delta` steps and returns the MIDI that goes out for it, nothing if the
+    /// move was too small to show up in the knob's encoding. `fine` turns acceleration off and
+    /// slows the knob down by `FINE_DIVISOR` instead.
+    pub fn turn(&mut self, delta: i32, fine: bool) -> Vec<Message> {
+        let turns = self.turns(delta, fine);
+
+        let last_position = self.position;
+        let last_output = self.output_position();
+
+        self.position = (self.position + turns).clamp(0.0, 1.0);
+
+        match self.encoding {
+            KnobEncoding::Absolute => {
+                self.catch_up(last_position);
+
+                if self.value_at(last_output) == self.value() {
+                    return vec![];
+                }
+
+                self.value_msgs()
+            }
+
+            KnobEncoding::Absolute14 | KnobEncoding::PitchBend => {
+                self.catch_up(last_position);
+
+                if self.value14_at(last_output) == self.value14_at(self.output_position()) {
+                    return vec![];
+                }
+
+                self.value_msgs()
+            }
+
+            encoding => {
+                self.residue += turns * self.steps_per_turn * self.ticks_per_step;
+                let ticks = self.residue.trunc();
+                self.residue -= ticks;
+
+                if ticks == 0.0 {
+                    return vec![];
+                }
+
+                vec![Message::ControlChange(self.channel, self.cc, encoding.relative_value(ticks as i32))]
+            }
+        }
+    }
+
+    /// Tells the knob which value its parameter is at. If that isn't where the knob is, the
+    /// knob's takeover decides how it gets there.
+    pub fn set_parameter(&mut self, value: U7) {
+        if self.takeover == Takeover::Off || self.min == self.max {
+            return;
+        }
+
+        let parameter = ((value as f32 - self.min as f32) / (self.max as f32 - self.min as f32)).clamp(0.0, 1.0);
+
+        self.parameter = if self.value_at(parameter) == self.value_at(self.position) {
+            None
+        } else {
+            Some(parameter)
+        };
+    }
+
+    fn catch_up(&mut self, last_position: f32) {
+        let parameter = match self.parameter {
+            Some(parameter) => parameter,
+            None => return,
+        };
+
+        if self.position == last_position {
+            return;
+        }
+
+        self.parameter = match self.takeover {
+            Takeover::Off => None,
+
+            Takeover::Pickup => {
+                let passed = (last_position - parameter) * (self.position - parameter) <= 0.0;
+
+                if passed || self.value_at(parameter) == self.value_at(self.position) {
+                    None
+                } else {
+                    Some(parameter)
+                }
+            }
+
+            Takeover::Scale => {
+                let parameter = if self.position > last_position {
+                    parameter + (self.position - last_position) * (1.0 - parameter) / (1.0 - last_position)
+                } else {
+                    parameter - (last_position - self.position) * parameter / last_position
+                };
+
+                if self.value_at(parameter) == self.value_at(self.position) {
+                    None
+                } else {
+                    Some(parameter)
+                }
+            }
+        };
+    }
+
+    fn output_position(&self) -> f32 {
+        self.parameter.unwrap_or(self.position)
+    }
+
+    /// How far `delta` steps move the knob, in turns, after acceleration or fine mode.
+    fn turns(&mut self, delta: i32, fine: bool) -> f32 {
+        let turns = delta as f32 / self.steps_per_turn;
+
+        let now = Instant::now();
+        let last_move = self.last_move.replace(now);
+
+        if fine {
+            return turns / FINE_DIVISOR;
+        }
+
+        let turns_per_second = match last_move {
+            Some(last_move) => {
+                // a millisecond floor, so two reports arriving back to back don't shoot off
+                let elapsed = now.duration_since(last_move).as_secs_f32().max(0.001);
+                turns.abs() / elapsed
+            }
+            None => 0.0,
+        };
+
+        turns * self.acceleration.factor(turns_per_second)
+    }
+
+    /// The channel and CC this knob sets a value on, none for the relative encodings and Pitch
+    /// Bend.
+    pub fn parameter(&self) -> Option<(Channel, U7)> {
+        match self.encoding {
+            KnobEncoding::Absolute | KnobEncoding::Absolute14 => Some((self.channel, self.cc)),
+            _ => None,
+        }
+    }
+
+    /// Where the knob is, from 0 to 1.
+    pub fn position(&self) -> f32 {
+        self.position
+    }
+
+    /// Puts the knob somewhere without sending anything, it's in sync with its parameter there.
+    pub fn set_position(&mut self, position: f32) {
+        self.position = position.clamp(0.0, 1.0);
+        self.parameter = None;
+    }
+
+    /// Where the knob starts, and goes back to on a reset.
+    pub fn set_default_position(&mut self, position: f32) {
+        self.default_position = position.clamp(0.0, 1.0);
+        self.set_position(position);
+    }
+
+    /// Puts the knob back where it started and returns the MIDI that takes the parameter along.
+    pub fn reset(&mut self) -> Vec<Message> {
+        let position = self.default_position;
+        self.set_position(position);
+        self.residue = 0.0;
+
+        self.value_msgs()
+    }
+
+    /// The value the knob sends, which is the parameter's while it's catching up.
+    pub fn value(&self) -> U7 {
+        self.value_at(self.output_position())
+    }
+
+    /// The MIDI that puts the parameter where the knob is, nothing for the relative encodings.
+    pub fn value_msgs(&self) -> Vec<Message> {
+        match self.encoding {
+            KnobEncoding::Absolute => vec![Message::ControlChange(self.channel, self.cc, self.value())],
+
+            KnobEncoding::Absolute14 => {
+                let value14 = self.value14_at(self.output_position());
+
+                vec![
+                    Message::ControlChange(self.channel, self.cc, (value14 >> 7) as U7),
+                    Message::ControlChange(self.channel, self.cc + 32, (value14 & 0x7F) as U7),
+                ]
+            }
+
+            KnobEncoding::PitchBend => vec![Message::PitchBend(self.channel, self.value14_at(self.output_position()))],
+
+            _ => vec![],
+        }
+    }
+
+    fn value_at(&self, position: f32) -> U7 {
+        (self.min as f32 + position * (self.max as f32 - self.min as f32)).round() as U7
+    }
+
+    fn value14_at(&self, position: f32) -> u16 {
+        let min = self.min as f32 * 16383.0 / 127.0;
+        let max = self.max as f32 * 16383.0 / 127.0;
+
+        (min + position * (max - min)).round() as u16
+    }
+}
+
+/// One set of assignments for the 8 knobs, Page Left/Right go through them.
+pub struct KnobPage {
+    pub name: String,
+    pub knobs: Vec<Knob>,
+}
+
+impl KnobPage {
+    /// The same CC on channels 1-8, one knob per group.
+    fn per_group(name: &str, knob_name: &str, cc: U7) -> Self {
+        let knobs = MIDI_CHANNELS[..8]
+            .iter()
+            .enumerate()
+            .map(|(group_idx, &channel)| {
+                let mut knob = Knob::new(cc, KNOB_STEPS_PER_TURN);
+                knob.name = format!("{} {}", knob_name, GROUP_NAMES[group_idx]);
+                knob.channel = channel;
+                knob
+            })
+            .collect();
+
+        KnobPage {
+            name: name.to_string(),
+            knobs,
+        }
+    }
+}
+
+/// What the knobs do. Volume, Swing and Tempo swap the knob pages for a fixed set of knobs
+/// while their button is lit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum KnobFunction {
+    Pages,
+    Volume,
+    Swing,
+    Tempo,
+}
+
+pub struct KnobFunctions {
+    pub volume: KnobPage,
+    pub swing: KnobPage,
+    pub tempo: KnobPage,
+}
+
+impl KnobFunctions {
+    pub fn new() -> Self {
+        let mut tempo = Knob::new(TEMPO_CC, KNOB_STEPS_PER_TURN);
+        tempo.name = "Tempo".to_string();
+        tempo.set_default_position((DEFAULT_BPM - TEMPO_MIN_BPM) / (TEMPO_MAX_BPM - TEMPO_MIN_BPM));
+
+        KnobFunctions {
+            volume: KnobPage::per_group("Volume", "Vol", VOLUME_CC),
+            swing: KnobPage::per_group("Swing", "Swg", SWING_CC),
+            tempo: KnobPage {
+                name: "Tempo".to_string(),
+                knobs: vec![tempo],
+            },
+        }
+    }
+
+    pub fn get_mut(&mut self, function: KnobFunction) -> Option<&mut KnobPage> {
+        match function {
+            KnobFunction::Pages => None,
+            KnobFunction::Volume => Some(&mut self.volume),
+            KnobFunction::Swing => Some(&mut self.swing),
+            KnobFunction::Tempo => Some(&mut self.tempo),
+        }
+    }
+
+    pub fn bpm(&self) -> f32 {
+        TEMPO_MIN_BPM + self.tempo.knobs[0].position() * (TEMPO_MAX_BPM - TEMPO_MIN_BPM)
+    }
+
+    /// Returns the tempo knob's MIDI for the new tempo.
+    pub fn set_bpm(&mut self, bpm: f32) -> Vec<Message> {
+        let knob = &mut self.tempo.knobs[0];
+        let last_value = knob.value();
+
+        knob.set_position((bpm - TEMPO_MIN_BPM) / (TEMPO_MAX_BPM - TEMPO_MIN_BPM));
+
+        if knob.value() == last_value {
+            return vec![];
+        }
+
+        vec![Message::ControlChange(knob.channel, knob.cc, knob.value())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cc(cc: U7, value: U7) -> Message {
+        Message::ControlChange(Ch1, cc, value)
+    }
+
+    // 128 steps to a turn, so a step is one relative tick
+    fn knob(encoding: KnobEncoding) -> Knob {
+        let mut knob = Knob::new(1, 128);
+        knob.encoding = encoding;
+        knob
+    }
+
+    #[test]
+    fn absolute() {
+        let mut knob = knob(KnobEncoding::Absolute);
+
+        assert_eq!(knob.turn(64, false), vec![cc(1, 64)]);
+        assert_eq!(knob.turn(256, false), vec![cc(1, 127)]);
+        assert_eq!(knob.turn(1, false), vec![]);
+    }
+
+    #[test]
+    fn absolute_too_small_to_send() {
+        let mut knob = Knob::new(16, 1000);
+
+        assert_eq!(knob.turn(1, false), vec![]);
+        assert_eq!(knob.position(), 0.001);
+    }
+
+    #[test]
+    fn absolute14() {
+        let mut knob = knob(KnobEncoding::Absolute14);
+
+        assert_eq!(knob.turn(64, false), vec![cc(1, 64), cc(33, 0)]);
+        assert_eq!(knob.turn(-1, false), vec![cc(1, 63), cc(33, 0)]);
+    }
+
+    #[test]
+    fn pitch_bend() {
+        let mut knob = knob(KnobEncoding::PitchBend);
+        knob.set_default_position(0.5);
+
+        assert_eq!(knob.value_msgs(), vec![Message::PitchBend(Ch1, 8192)]);
+        assert_eq!(knob.turn(64, false), vec![Message::PitchBend(Ch1, 16383)]);
+        assert_eq!(knob.reset(), vec![Message::PitchBend(Ch1, 8192)]);
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(knob(KnobEncoding::TwosComplement).turn(2, false), vec![cc(1, 2)]);
+        assert_eq!(knob(KnobEncoding::TwosComplement).turn(-1, false), vec![cc(1, 127)]);
+        assert_eq!(knob(KnobEncoding::BinaryOffset).turn(1, false), vec![cc(1, 65)]);
+        assert_eq!(knob(KnobEncoding::BinaryOffset).turn(-1, false), vec![cc(1, 63)]);
+        assert_eq!(knob(KnobEncoding::SignMagnitude).turn(3, false), vec![cc(1, 3)]);
+        assert_eq!(knob(KnobEncoding::SignMagnitude).turn(-2, false), vec![cc(1, 66)]);
+    }
+
+    #[test]
+    fn relative_clamps_at_63_ticks() {
+        assert_eq!(knob(KnobEncoding::TwosComplement).turn(100, false), vec![cc(1, 63)]);
+        assert_eq!(knob(KnobEncoding::SignMagnitude).turn(-100, false), vec![cc(1, 127)]);
+    }
+
+    #[test]
+    fn relative_keeps_the_residue() {
+        let mut knob = knob(KnobEncoding::TwosComplement);
+
+        // 4 steps in fine mode are half a tick each time
+        assert_eq!(knob.turn(4, true), vec![]);
+        assert_eq!(knob.turn(4, true), vec![cc(1, 1)]);
+        assert_eq!(knob.parameter(), None);
+    }
+
+    #[test]
+    fn relative_ticks_per_step() {
+        // an encoder click is a tick, not a sixteenth of a turn's 128
+        let mut encoder = Knob::new(15, 16);
+        encoder.encoding = KnobEncoding::TwosComplement;
+        encoder.ticks_per_step = 1.0;
+
+        assert_eq!(encoder.turn(1, false), vec![cc(15, 1)]);
+        assert_eq!(encoder.turn(-3, false), vec![cc(15, 125)]);
+
+        // the absolute position still goes by turns
+        assert_eq!(encoder.position(), 0.0);
+        encoder.encoding = KnobEncoding::Absolute;
+        assert_eq!(encoder.turn(8, false), vec![cc(15, 64)]);
+    }
+
+    #[test]
+    fn fine() {
+        let mut knob = knob(KnobEncoding::Absolute);
+        knob.acceleration = Acceleration::Linear(100.0);
+
+        assert_eq!(knob.turn(64, true), vec![cc(1, 8)]);
+        assert_eq!(knob.position(), 0.5 / FINE_DIVISOR);
+    }
+
+    #[test]
+    fn min_max() {
+        let mut knob = knob(KnobEncoding::Absolute);
+        knob.min = 100;
+        knob.max = 20;
+
+        assert_eq!(knob.value(), 100);
+        assert_eq!(knob.turn(128, false), vec![cc(1, 20)]);
+    }
+
+    #[test]
+    fn takeover_off_jumps() {
+        let mut knob = knob(KnobEncoding::Absolute);
+        knob.set_parameter(100);
+
+        assert_eq!(knob.turn(1, false), vec![cc(1, 1)]);
+    }
+
+    #[test]
+    fn takeover_pickup() {
+        let mut knob = knob(KnobEncoding::Absolute);
+        knob.takeover = Takeover::Pickup;
+        knob.set_parameter(100);
+
+        assert_eq!(knob.value(), 100);
+        assert_eq!(knob.turn(32, false), vec![]);
+        assert_eq!(knob.turn(64, false), vec![]);
+
+        // passing 100 picks it up
+        assert_eq!(knob.turn(16, false), vec![cc(1, 111)]);
+        assert_eq!(knob.turn(-16, false), vec![cc(1, 95)]);
+    }
+
+    #[test]
+    fn takeover_scale() {
+        let mut knob = knob(KnobEncoding::Absolute);
+        knob.takeover = Takeover::Scale;
+        knob.set_parameter(64);
+
+        // half way up the knob is half way from 64 to the top
+        assert_eq!(knob.turn(64, false), vec![cc(1, 96)]);
+        assert_eq!(knob.turn(64, false), vec![cc(1, 127)]);
+        assert_eq!(knob.turn(-64, false), vec![cc(1, 64)]);
+    }
+
+    #[test]
+    fn takeover_in_sync() {
+        let mut knob = knob(KnobEncoding::Absolute);
+        knob.takeover = Takeover::Pickup;
+        knob.set_parameter(0);
+
+        assert_eq!(knob.turn(64, false), vec![cc(1, 64)]);
+    }
+
+    #[test]
+    fn fits() {
+        assert!(KnobEncoding::Absolute14.fits(31));
+        assert!(!KnobEncoding::Absolute14.fits(32));
+        assert!(KnobEncoding::Absolute.fits(100));
+    }
+}
@@ -0,0 +1,222 @@
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+/// The 128 colors a velocity picks on a Launchpad, so DAW scripts written for one color the
+/// pads the same.
+pub const PALETTE: [u32; 128] = [
+    0x000000, 0x1E1E1E, 0x7F7F7F, 0xFFFFFF, 0xFF4C4C, 0xFF0000, 0x590000, 0x190000,
+    0xFFBD6C, 0xFF5400, 0x591D00, 0x271B00, 0xFFFF4C, 0xFFFF00, 0x595900, 0x191900,
+    0x88FF4C, 0x54FF00, 0x1D5900, 0x142B00, 0x4CFF4C, 0x00FF00, 0x005900, 0x001900,
+    0x4CFF5E, 0x00FF19, 0x00590D, 0x001902, 0x4CFF88, 0x00FF55, 0x00591D, 0x001F12,
+    0x4CFFB7, 0x00FF99, 0x005935, 0x001912, 0x4CC3FF, 0x00A9FF, 0x004152, 0x001019,
+    0x4C88FF, 0x0055FF, 0x001D59, 0x000819, 0x4C4CFF, 0x0000FF, 0x000059, 0x000019,
+    0x874CFF, 0x5400FF, 0x190064, 0x0F0030, 0xFF4CFF, 0xFF00FF, 0x590059, 0x190019,
+    0xFF4C87, 0xFF0054, 0x59001D, 0x220013, 0xFF1500, 0x993500, 0x795100, 0x436400,
+    0x033900, 0x005735, 0x00547F, 0x0000FF, 0x00454F, 0x2500CC, 0x7F7F7F, 0x202020,
+    0xFF0000, 0xBDFF2D, 0xAFED06, 0x64FF09, 0x108B00, 0x00FF87, 0x00A9FF, 0x002AFF,
+    0x3F00FF, 0x7A00FF, 0xB21A7D, 0x402100, 0xFF4A00, 0x88E106, 0x72FF15, 0x00FF00,
+    0x3BFF26, 0x59FF71, 0x38FFCC, 0x5B8AFF, 0x3151C6, 0x877FE9, 0xD31DFF, 0xFF005D,
+    0xFF7F00, 0xB9B000, 0x90FF00, 0x835D07, 0x392B00, 0x144C10, 0x0D5038, 0x15152A,
+    0x16205A, 0x693C1C, 0xA8000A, 0xDE513D, 0xD86A1C, 0xFFE126, 0x9EE12F, 0x67B50F,
+    0x1E1E30, 0xDCFF6B, 0x80FFBD, 0x9A99FF, 0x8E66FF, 0x404040, 0x757575, 0xE0FFFF,
+    0xA00000, 0x350000, 0x1AD000, 0x074200, 0xB9B000, 0x3F3100, 0xB35F00, 0x4B1502,
+];
+
+/// How a light changes over a beat. On a Launchpad it's the channel the note comes in on.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Animation {
+    Static,
+    /// Between its color and the static one before it, half a beat each.
+    Flash,
+    /// Fades down and back up.
+    Pulse,
+}
+
+/// A light set by feedback, with the static light it flashes against.
+#[derive(Copy, Clone, Debug)]
+pub struct Lit {
+    pub color: u32,
+    pub brightness: f32,
+    pub animation: Animation,
+    pub base: (u32, f32),
+}
+
+impl Lit {
+    /// A light that flashes or pulses keeps what was lit before it to flash against.
+    pub fn new(color: u32, brightness: f32, animation: Animation, last: Option<Lit>) -> Self {
+        let base = match last {
+            Some(last) if last.animation == Animation::Static => (last.color, last.brightness),
+            Some(last) => last.base,
+            None => (0, 0.0),
+        };
+
+        Lit {
+            color,
+            brightness,
+            animation,
+            base,
+        }
+    }
+
+    /// The color and brightness `phase` of the way through a beat.
+    pub fn at(&self, phase: f32) -> (u32, f32) {
+        match self.animation {
+            Animation::Static => (self.color, self.brightness),
+
+            Animation::Flash if phase < 0.5 => (self.color, self.brightness),
+            Animation::Flash => self.base,
+
+            Animation::Pulse => (self.color, self.brightness * (0.6 + 0.4 * (2.0 * PI * phase).cos())),
+        }
+    }
+}
+
+// without clock the lights run at 120 BPM, and clock that stopped for this long is gone
+const FREE_RUNNING_BEAT: Duration = Duration::from_millis(500);
+const CLOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+const CLOCKS_PER_BEAT: u32 = 24;
+
+/// Where in the beat the lights are, from the MIDI clock that comes in when there is any.
+pub struct Beat {
+    started: Instant,
+
+    clocks: u32,
+    last_clock: Option<Instant>,
+    clock_interval: Duration,
+}
+
+impl Beat {
+    pub fn new() -> Self {
+        Beat {
+            started: Instant::now(),
+
+            clocks: 0,
+            last_clock: None,
+            clock_interval: FREE_RUNNING_BEAT / CLOCKS_PER_BEAT,
+        }
+    }
+
+    /// Start lines the beat up with the DAW's.
+    pub fn start(&mut self) {
+        self.clocks = 0;
+        self.last_clock = None;
+    }
+
+    pub fn clock(&mut self, now: Instant) {
+        if let Some(last_clock) = self.last_clock {
+            let interval = now.duration_since(last_clock);
+
+            if interval < CLOCK_TIMEOUT {
+                // smoothed, clock over ALSA jitters
+                self.clock_interval = (self.clock_interval * 3 + interval) / 4;
+            }
+
+            self.clocks = (self.clocks + 1) % CLOCKS_PER_BEAT;
+        }
+
+        self.last_clock = Some(now);
+    }
+
+    /// How long a beat is at the clock's tempo, if clock is coming in.
+    pub fn clock_beat(&self, now: Instant) -> Option<Duration> {
+        match self.last_clock {
+            Some(last_clock) if now.duration_since(last_clock) < CLOCK_TIMEOUT => Some(self.clock_interval * CLOCKS_PER_BEAT),
+            _ => None,
+        }
+    }
+
+    /// How far into the beat `now` is, from 0 to 1.
+    pub fn phase(&self, now: Instant) -> f32 {
+        match self.last_clock {
+            Some(last_clock) if now.duration_since(last_clock) < CLOCK_TIMEOUT => {
+                let since_clock = now.duration_since(last_clock).as_secs_f32() / self.clock_interval.as_secs_f32();
+                (self.clocks as f32 + since_clock.min(1.0)) / CLOCKS_PER_BEAT as f32
+            }
+
+            _ => {
+                let beats = now.duration_since(self.started).as_secs_f32() / FREE_RUNNING_BEAT.as_secs_f32();
+                beats.fract()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn static_light() {
+        let lit = Lit::new(0xFF0000, 0.8, Animation::Static, None);
+
+        assert_eq!(lit.at(0.0), (0xFF0000, 0.8));
+        assert_eq!(lit.at(0.7), (0xFF0000, 0.8));
+    }
+
+    #[test]
+    fn flash_against_the_last_static_light() {
+        let red = Lit::new(0xFF0000, 1.0, Animation::Static, None);
+        let green = Lit::new(0x00FF00, 1.0, Animation::Flash, Some(red));
+
+        assert_eq!(green.at(0.0), (0x00FF00, 1.0));
+        assert_eq!(green.at(0.49), (0x00FF00, 1.0));
+        assert_eq!(green.at(0.5), (0xFF0000, 1.0));
+        assert_eq!(green.at(0.99), (0xFF0000, 1.0));
+
+        // a flash over a flash still flashes against red
+        let blue = Lit::new(0x0000FF, 1.0, Animation::Flash, Some(green));
+        assert_eq!(blue.at(0.75), (0xFF0000, 1.0));
+    }
+
+    #[test]
+    fn flash_against_nothing() {
+        let lit = Lit::new(0x00FF00, 1.0, Animation::Flash, None);
+
+        assert_eq!(lit.at(0.75), (0, 0.0));
+    }
+
+    #[test]
+    fn pulse() {
+        let lit = Lit::new(0x00FF00, 1.0, Animation::Pulse, None);
+
+        assert_eq!(lit.at(0.0), (0x00FF00, 1.0));
+
+        let (color, brightness) = lit.at(0.5);
+        assert_eq!(color, 0x00FF00);
+        assert!((brightness - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn free_running_beat() {
+        let beat = Beat::new();
+        let now = beat.started;
+
+        assert_eq!(beat.clock_beat(now), None);
+        assert_eq!(beat.phase(now + Duration::from_millis(250)), 0.5);
+        assert_eq!(beat.phase(now + Duration::from_millis(1125)), 0.25);
+    }
+
+    #[test]
+    fn clocked_beat() {
+        let mut beat = Beat::new();
+        let start = beat.started;
+        let tick = Duration::from_millis(25);
+
+        // the clock's tempo takes a few beats to settle in
+        beat.start();
+        for clock in 0..=102 {
+            beat.clock(start + tick * clock);
+        }
+
+        let now = start + tick * 102;
+
+        // 25ms clocks, 24 to a beat, are 100 BPM
+        let beat_ms = beat.clock_beat(now).unwrap().as_secs_f32() * 1000.0;
+        assert!((beat_ms - 600.0).abs() < 0.1);
+        assert_eq!(beat.phase(now), 0.25);
+
+        // stopped clock goes back to running free
+        assert_eq!(beat.clock_beat(now + CLOCK_TIMEOUT), None);
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+
+use midi::Channel::Ch1;
+use midi::{Manufacturer, Message, U7};
+
+use base::{MaschineButton, KNOB_STEPS_PER_TURN};
+use knobs::{Knob, KnobEncoding};
+use midi_out::MidiOut;
+use utils::GROUP_BUTTONS;
+
+// every SysEx either way starts with Mackie's manufacturer ID and the MCU's device ID
+const MACKIE: Manufacturer = Manufacturer::ThreeByte(0x00, 0x00, 0x66);
+const MCU: U7 = 0x14;
+
+const DEVICE_QUERY: U7 = 0x00;
+const HOST_CONNECTION_QUERY: U7 = 0x01;
+const HOST_CONNECTION_REPLY: U7 = 0x02;
+const HOST_CONNECTION_CONFIRMATION: U7 = 0x03;
+const LCD: U7 = 0x12;
+
+const SERIAL: [U7; 7] = [b'M', b'A', b'S', b'C', b'H', b'M', b'K'];
+
+const VPOT_CC: U7 = 0x10;
+const VPOT_RING_CC: U7 = 0x30;
+
+// the first strip's note of each row of strip buttons
+const SOLO: U7 = 0x08;
+const MUTE: U7 = 0x10;
+const SELECT: U7 = 0x18;
+
+pub const STRIPS: usize = 8;
+// the LCD is two rows of 7 characters for each strip
+const LCD_STRIP_WIDTH: usize = 7;
+const LCD_ROW: usize = STRIPS * LCD_STRIP_WIDTH;
+
+/// The buttons that do what they say on an MCU, with its notes for them.
+const BUTTONS: [(MaschineButton, U7); 17] = [
+    (MaschineButton::Stepleft, 0x5B),
+    (MaschineButton::Stepright, 0x5C),
+    (MaschineButton::Erase, 0x5D),
+    (MaschineButton::Play, 0x5E),
+    (MaschineButton::Rec, 0x5F),
+    (MaschineButton::Pageleft, 0x2E),
+    (MaschineButton::Pageright, 0x2F),
+    (MaschineButton::Navleft, 0x30),
+    (MaschineButton::Navright, 0x31),
+    (MaschineButton::F1, 0x36),
+    (MaschineButton::F2, 0x37),
+    (MaschineButton::F3, 0x38),
+    (MaschineButton::F4, 0x39),
+    (MaschineButton::F5, 0x3A),
+    (MaschineButton::F6, 0x3B),
+    (MaschineButton::F7, 0x3C),
+    (MaschineButton::F8, 0x3D),
+];
+
+/// The MK2 as a Mackie Control Universal on a port of its own. Knobs are the V-Pots, group
+/// buttons select strips, or mute or solo them while Mute or Solo is held, and the transport
+/// and bank buttons are the MCU's. The DAW lights the buttons and writes strip names and
+/// V-Pot rings to the screen, one line per strip.
+pub struct Mackie<'a> {
+    midi: MidiOut<'a>,
+
+    vpots: Vec<Knob>,
+    // which row of strip buttons the group buttons are
+    strip_buttons: U7,
+
+    leds: BTreeMap<U7, bool>,
+    lcd: Vec<u8>,
+    rings: [U7; STRIPS],
+    // the screen lines the DAW changed since they were last drawn
+    dirty: [bool; STRIPS],
+}
+
+impl<'a> Mackie<'a> {
+    pub fn new(midi: MidiOut<'a>) -> Self {
+        let vpots = (0..STRIPS)
+            .map(|strip| {
+                let mut vpot = Knob::new(VPOT_CC + strip as U7, KNOB_STEPS_PER_TURN);
+                vpot.encoding = KnobEncoding::SignMagnitude;
+                vpot
+            })
+            .collect();
+
+        Mackie {
+            midi,
+
+            vpots,
+            strip_buttons: SELECT,
+
+            leds: BTreeMap::new(),
+            lcd: vec![b' '; 2 * LCD_ROW],
+            rings: [0; STRIPS],
+            dirty: [true; STRIPS],
+        }
+    }
+
+    /// The port the DAW talks to the MCU on.
+    pub fn port(&self) -> i32 {
+        self.midi.seq_port.id()
+    }
+
+    pub fn turn_vpot(&mut self, strip: usize, delta: i32, fine: bool) {
+        if let Some(vpot) = self.vpots.get_mut(strip) {
+            for msg in vpot.turn(delta, fine) {
+                self.midi.send(&msg);
+            }
+        }
+    }
+
+    /// Sends the MCU button for a press or release, returns whether `btn` is one.
+    pub fn button(&mut self, btn: MaschineButton, is_down: bool) -> bool {
+        let note = match btn {
+            MaschineButton::Mute | MaschineButton::Solo => {
+                let row = if btn == MaschineButton::Mute { MUTE } else { SOLO };
+
+                if is_down {
+                    self.strip_buttons = row;
+                } else if self.strip_buttons == row {
+                    self.strip_buttons = SELECT;
+                }
+
+                return true;
+            }
+
+            _ => match GROUP_BUTTONS.iter().position(|&group| group == btn) {
+                Some(strip) => self.strip_buttons + strip as U7,
+                None => match BUTTONS.iter().find(|&&(button, _)| button == btn) {
+                    Some(&(_, note)) => note,
+                    None => return false,
+                },
+            },
+        };
+
+        self.midi.send(&Message::NoteOn(Ch1, note, if is_down { 127 } else { 0 }));
+        true
+    }
+
+    /// Takes in what the DAW sent to the MCU.
+    pub fn receive(&mut self, msg: &Message) {
+        match *msg {
+            // blinking LEDs stay lit
+            Message::NoteOn(Ch1, note, velocity) => {
+                self.leds.insert(note, velocity > 0);
+            }
+            Message::NoteOff(Ch1, note, _) => {
+                self.leds.insert(note, false);
+            }
+
+            Message::ControlChange(Ch1, cc, value) if (VPOT_RING_CC..VPOT_RING_CC + STRIPS as U7).contains(&cc) => {
+                let strip = (cc - VPOT_RING_CC) as usize;
+                self.rings[strip] = value;
+                self.dirty[strip] = true;
+            }
+
+            Message::SysEx(MACKIE, ref data) => self.receive_sysex(data),
+
+            _ => {}
+        }
+    }
+
+    fn receive_sysex(&mut self, data: &[U7]) {
+        if let Some(reply) = handshake_reply(data) {
+            return self.midi.send(&reply);
+        }
+
+        if let [MCU, LCD, offset, ref text @ ..] = *data {
+            for (idx, &c) in text.iter().enumerate() {
+                let pos = offset as usize + idx;
+
+                if let Some(lcd_char) = self.lcd.get_mut(pos) {
+                    *lcd_char = c;
+                    self.dirty[(pos % LCD_ROW) / LCD_STRIP_WIDTH] = true;
+                }
+            }
+        }
+    }
+
+    /// What the DAW has the buttons lit as.
+    pub fn lights(&self) -> Vec<(MaschineButton, bool)> {
+        let lit = |note: U7| self.leds.get(&note).cloned().unwrap_or(false);
+
+        BUTTONS
+            .iter()
+            .map(|&(btn, note)| (btn, lit(note)))
+            .chain(GROUP_BUTTONS.iter().enumerate().map(|(strip, &btn)| (btn, lit(self.strip_buttons + strip as U7))))
+            .chain(vec![
+                (MaschineButton::Mute, self.strip_buttons == MUTE),
+                (MaschineButton::Solo, self.strip_buttons == SOLO),
+            ])
+            .collect()
+    }
+
+    /// The screen lines that changed, each is a strip's two LCD cells and its V-Pot ring.
+    pub fn screen_lines(&mut self) -> Vec<(usize, String)> {
+        let mut lines = vec![];
+
+        for strip in 0..STRIPS {
+            if !self.dirty[strip] {
+                continue;
+            }
+
+            let cell = |row: usize| {
+                let start = row * LCD_ROW + strip * LCD_STRIP_WIDTH;
+                String::from_utf8_lossy(&self.lcd[start..start + LCD_STRIP_WIDTH]).into_owned()
+            };
+
+            lines.push((strip, format!("{} {} {}", cell(0), cell(1), ring(self.rings[strip]))));
+            self.dirty[strip] = false;
+        }
+
+        lines
+    }
+}
+
+/// The MCU's side of the handshake the DAW starts with a device query.
+fn handshake_reply(data: &[U7]) -> Option<Message> {
+    let reply = match *data {
+        [MCU, DEVICE_QUERY] => {
+            let mut reply = vec![MCU, HOST_CONNECTION_QUERY];
+            reply.extend(&SERIAL);
+            // the challenge, the DAW's reply to it isn't checked
+            reply.extend(&[0; 4]);
+            reply
+        }
+
+        [MCU, HOST_CONNECTION_REPLY, ..] => {
+            let mut confirmation = vec![MCU, HOST_CONNECTION_CONFIRMATION];
+            confirmation.extend(&SERIAL);
+            confirmation
+        }
+
+        _ => return None,
+    };
+
+    Some(Message::SysEx(MACKIE, reply))
+}
+
+/// The 11 LEDs of a V-Pot ring drawn as 5 characters. The ring's value has the mode in bits
+/// 4-5 and the position in bits 0-3, position 0 is all off.
+fn ring(value: U7) -> String {
+    let mode = (value >> 4) & 0x03;
+    let position = (value & 0x0F) as i32;
+
+    if !(1..=11).contains(&position) {
+        return "     ".to_string();
+    }
+
+    let at = (position - 1) * 4 / 10;
+
+    (0..5)
+        .map(|led| {
+            let lit = match mode {
+                // dot
+                0 => led == at,
+                // boost/cut, from the center
+                1 => (led >= at && led <= 2) || (led <= at && led >= 2),
+                // wrap, from the left
+                2 => led <= at,
+                // spread, out from the center
+                _ => (led - 2).abs() <= (position - 1) * 2 / 5,
+            };
+
+            if lit {
+                '+'
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mackie(data: &[U7]) -> Message {
+        Message::SysEx(MACKIE, data.to_vec())
+    }
+
+    #[test]
+    fn handshake() {
+        assert_eq!(
+            handshake_reply(&[MCU, DEVICE_QUERY]),
+            Some(mackie(&[MCU, 0x01, b'M', b'A', b'S', b'C', b'H', b'M', b'K', 0, 0, 0, 0]))
+        );
+
+        let mut reply = vec![MCU, HOST_CONNECTION_REPLY];
+        reply.extend(&SERIAL);
+        reply.extend(&[1, 2, 3, 4]);
+
+        assert_eq!(
+            handshake_reply(&reply),
+            Some(mackie(&[MCU, 0x03, b'M', b'A', b'S', b'C', b'H', b'M', b'K']))
+        );
+    }
+
+    #[test]
+    fn no_handshake() {
+        assert_eq!(handshake_reply(&[MCU, LCD, 0, b'a']), None);
+        assert_eq!(handshake_reply(&[0x15, DEVICE_QUERY]), None);
+        assert_eq!(handshake_reply(&[]), None);
+    }
+
+    #[test]
+    fn rings() {
+        assert_eq!(ring(0x00), "     ");
+        assert_eq!(ring(0x0C), "     ");
+
+        // dot
+        assert_eq!(ring(0x01), "+----");
+        assert_eq!(ring(0x06), "--+--");
+        assert_eq!(ring(0x0B), "----+");
+
+        // boost/cut
+        assert_eq!(ring(0x11), "+++--");
+        assert_eq!(ring(0x16), "--+--");
+        assert_eq!(ring(0x1B), "--+++");
+
+        // wrap
+        assert_eq!(ring(0x21), "+----");
+        assert_eq!(ring(0x26), "+++--");
+        assert_eq!(ring(0x2B), "+++++");
+
+        // spread
+        assert_eq!(ring(0x31), "--+--");
+        assert_eq!(ring(0x3B), "+++++");
+    }
+}
@@ -0,0 +1,162 @@
+use std::time::{Duration, Instant};
+
+use mapping::ButtonAction;
+
+/// A wait between the steps of a macro, in milliseconds and beats at the current tempo.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Delay {
+    Delay {
+        #[serde(default)]
+        ms: u64,
+        #[serde(default)]
+        beats: f32,
+    },
+}
+
+/// One step of a macro from the [macros] table of mapping.toml, a delay or anything a button
+/// can do.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MacroStep {
+    Delay(Delay),
+    Action(ButtonAction),
+}
+
+struct Running {
+    steps: Vec<MacroStep>,
+    next: usize,
+    due: Instant,
+}
+
+/// The macros that are running. They're polled from the event loop rather than played out in
+/// one go, so their delays don't hold up the pads and buttons.
+pub struct Macros {
+    running: Vec<Running>,
+}
+
+impl Macros {
+    pub fn new() -> Self {
+        Macros { running: vec![] }
+    }
+
+    pub fn start(&mut self, steps: Vec<MacroStep>, now: Instant) {
+        self.running.push(Running { steps, next: 0, due: now });
+    }
+
+    /// The actions that are due by `now`, in order. Delays in beats are `beat` long each.
+    pub fn poll(&mut self, now: Instant, beat: Duration) -> Vec<ButtonAction> {
+        let mut actions = vec![];
+
+        for running in &mut self.running {
+            while running.due <= now && running.next < running.steps.len() {
+                match running.steps[running.next] {
+                    MacroStep::Delay(Delay::Delay { ms, beats }) => {
+                        // from when the delay was due, so late polls don't add up
+                        running.due += Duration::from_millis(ms) + beat.mul_f32(beats.max(0.0));
+                    }
+                    MacroStep::Action(ref action) => actions.push(action.clone()),
+                }
+
+                running.next += 1;
+            }
+        }
+
+        self.running.retain(|running| running.next < running.steps.len());
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Steps {
+        steps: Vec<MacroStep>,
+    }
+
+    fn steps(steps: &str) -> Vec<MacroStep> {
+        toml::from_str::<Steps>(&format!("steps = [{}]", steps)).unwrap().steps
+    }
+
+    fn numbers(actions: Vec<ButtonAction>) -> Vec<u8> {
+        actions
+            .into_iter()
+            .map(|action| match action {
+                ButtonAction::Cc { number, .. } => number,
+                action => panic!("not a cc: {:?}", action),
+            })
+            .collect()
+    }
+
+    const BEAT: Duration = Duration::from_millis(500);
+
+    #[test]
+    fn runs_up_to_the_first_delay() {
+        let start = Instant::now();
+        let mut macros = Macros::new();
+
+        macros.start(
+            steps(r#"{ type = "cc", number = 1 }, { type = "cc", number = 2 }, { type = "delay", ms = 100 }, { type = "cc", number = 3 }"#),
+            start,
+        );
+
+        assert_eq!(numbers(macros.poll(start, BEAT)), vec![1, 2]);
+        assert_eq!(numbers(macros.poll(start + Duration::from_millis(99), BEAT)), vec![]);
+        assert_eq!(numbers(macros.poll(start + Duration::from_millis(100), BEAT)), vec![3]);
+        assert!(macros.running.is_empty());
+    }
+
+    #[test]
+    fn beats_and_ms_add_up() {
+        let start = Instant::now();
+        let mut macros = Macros::new();
+
+        macros.start(steps(r#"{ type = "delay", ms = 10, beats = 2 }, { type = "cc", number = 1 }"#), start);
+
+        assert_eq!(numbers(macros.poll(start + Duration::from_millis(1009), BEAT)), vec![]);
+        assert_eq!(numbers(macros.poll(start + Duration::from_millis(1010), BEAT)), vec![1]);
+    }
+
+    #[test]
+    fn late_polls_dont_drift() {
+        let start = Instant::now();
+        let mut macros = Macros::new();
+
+        macros.start(
+            steps(r#"{ type = "delay", ms = 100 }, { type = "cc", number = 1 }, { type = "delay", ms = 100 }, { type = "cc", number = 2 }"#),
+            start,
+        );
+
+        // the first delay is polled late, the second still ends 200ms after the start
+        assert_eq!(numbers(macros.poll(start + Duration::from_millis(150), BEAT)), vec![1]);
+        assert_eq!(numbers(macros.poll(start + Duration::from_millis(200), BEAT)), vec![2]);
+    }
+
+    #[test]
+    fn one_late_poll_catches_up() {
+        let start = Instant::now();
+        let mut macros = Macros::new();
+
+        macros.start(
+            steps(r#"{ type = "cc", number = 1 }, { type = "delay", beats = 1 }, { type = "cc", number = 2 }, { type = "delay", beats = 1 }, { type = "cc", number = 3 }"#),
+            start,
+        );
+
+        assert_eq!(numbers(macros.poll(start + BEAT * 3, BEAT)), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn macros_run_side_by_side() {
+        let start = Instant::now();
+        let mut macros = Macros::new();
+
+        macros.start(steps(r#"{ type = "delay", ms = 100 }, { type = "cc", number = 1 }"#), start);
+        macros.start(steps(r#"{ type = "cc", number = 2 }, { type = "delay", ms = 50 }, { type = "cc", number = 3 }"#), start);
+
+        assert_eq!(numbers(macros.poll(start, BEAT)), vec![2]);
+        assert_eq!(numbers(macros.poll(start + Duration::from_millis(50), BEAT)), vec![3]);
+        assert_eq!(numbers(macros.poll(start + Duration::from_millis(100), BEAT)), vec![1]);
+    }
+}
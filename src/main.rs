@@ -15,8 +15,19 @@
 //  License along with this program.  If not, see
 //  <http://www.gnu.org/licenses/>.
 
+mod automation;
+mod gestures;
 mod handler;
+mod knobs;
+mod lights;
+mod macros;
+mod mackie;
+mod mapping;
+mod midi_out;
+mod modes;
 mod osc;
+mod profile;
+mod strip;
 mod utils;
 
 use std::env;
@@ -38,10 +49,18 @@ use nix::{fcntl, sys};
 extern crate alsa_seq;
 extern crate midi;
 
+#[macro_use]
+extern crate serde;
+extern crate toml;
+
 use alsa_seq::*;
 use midi::*;
 // use devices::mk2::Mikro;
 use handler::MHandler;
+use mackie::Mackie;
+use mapping::{Mapping, MAPPING_PATH};
+use midi_out::MidiOut;
+use profile::{Profile, PROFILE_PATH};
 
 mod base;
 mod devices;
@@ -68,7 +87,7 @@ fn main() {
 
     let osc_socket = UdpSocket::bind("127.0.0.1:42434").unwrap();
 
-    let seq_handle = SequencerHandle::open("maschine.rs", HandleOpenStreams::Output).unwrap();
+    let seq_handle = SequencerHandle::open("maschine.rs", HandleOpenStreams::Duplex).unwrap();
     let seq_port = seq_handle
         .create_port(
             "Pads MIDI",
@@ -77,9 +96,47 @@ fn main() {
         )
         .unwrap();
 
+    let feedback_port = seq_handle
+        .create_port(
+            "Feedback MIDI",
+            PortCapabilities::PORT_CAPABILITY_WRITE | PortCapabilities::PORT_CAPABILITY_SUBS_WRITE,
+            PortType::MidiGeneric,
+        )
+        .unwrap();
+
     let mut device = devices::mk2::Mikro::new(dev_fd);
 
-    let mut handler = MHandler::new(&seq_handle, &seq_port, &osc_socket);
+    let profile = Profile::load(Path::new(PROFILE_PATH));
+
+    let mapping = Mapping::load(Path::new(MAPPING_PATH));
+
+    let mackie_port = if profile.mackie_control {
+        let port = seq_handle
+            .create_port(
+                "Mackie Control",
+                PortCapabilities::PORT_CAPABILITY_READ
+                    | PortCapabilities::PORT_CAPABILITY_SUBS_READ
+                    | PortCapabilities::PORT_CAPABILITY_WRITE
+                    | PortCapabilities::PORT_CAPABILITY_SUBS_WRITE
+                    | PortCapabilities::PORT_CAPABILITY_DUPLEX,
+                PortType::MidiGeneric,
+            )
+            .unwrap();
+
+        Some(port)
+    } else {
+        None
+    };
+
+    let mut handler = MHandler::new(&seq_handle, &seq_port, &osc_socket, &profile, mapping);
+
+    handler.feedback_port = Some(feedback_port.id());
+    handler.mackie = mackie_port.as_ref().map(|seq_port| {
+        Mackie::new(MidiOut {
+            seq_handle: &seq_handle,
+            seq_port,
+        })
+    });
 
     device.clear_screen();
 
@@ -94,5 +151,9 @@ fn main() {
         device.set_pad_light(i, handler.pad_color(), PAD_RELEASED_BRIGHTNESS);
     }
 
+    if handler.knob_pages.len() > 1 {
+        handler.show_knob_page(&mut device);
+    }
+
     handler::ev_loop(&mut device, &mut handler);
 }
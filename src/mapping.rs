@@ -0,0 +1,827 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use hsl::HSL;
+use midi::{Channel, Manufacturer, Message, U14, U7};
+
+use base::MaschineButton;
+use gestures::{Control, Gesture, GestureTiming};
+use knobs::KnobPage;
+use lights::{Animation, PALETTE};
+use macros::MacroStep;
+use modes::SessionConfig;
+use osc::osc_button_to_btn_map;
+use profile::{KnobConfig, KnobPageConfig};
+use utils::midi_channel;
+
+/// Read from the working directory at startup, like profile.toml.
+pub const MAPPING_PATH: &str = "mapping.toml";
+
+// the shipped mapping.toml is also the built-in one
+const DEFAULT_MAPPING: &str = include_str!("../mapping.toml");
+
+/// Something in maschine.rs itself a button can do.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InternalAction {
+    Volume,
+    Swing,
+    Tempo,
+    TapTempo,
+    PageLeft,
+    PageRight,
+    NextPadMode,
+    RecArm,
+}
+
+/// MIDI Machine Control commands, for DAWs that follow MMC without being taught.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MmcCommand {
+    Stop,
+    Play,
+    DeferredPlay,
+    FastForward,
+    Rewind,
+    RecordStrobe,
+    RecordExit,
+    Pause,
+    Locate,
+}
+
+impl MmcCommand {
+    /// The universal real time SysEx for the command, `time` is where Locate goes as hours,
+    /// minutes, seconds and frames.
+    pub fn sysex(self, device: U7, time: [U7; 4]) -> Message {
+        let [hours, minutes, seconds, frames] = time;
+
+        let command = match self {
+            MmcCommand::Stop => vec![0x01],
+            MmcCommand::Play => vec![0x02],
+            MmcCommand::DeferredPlay => vec![0x03],
+            MmcCommand::FastForward => vec![0x04],
+            MmcCommand::Rewind => vec![0x05],
+            MmcCommand::RecordStrobe => vec![0x06],
+            MmcCommand::RecordExit => vec![0x07],
+            MmcCommand::Pause => vec![0x09],
+            MmcCommand::Locate => vec![0x44, 0x06, 0x01, hours, minutes, seconds, frames, 0],
+        };
+
+        let mut data = vec![device, 0x06];
+        data.extend(command);
+
+        Message::SysEx(Manufacturer::OneByte(0x7F), data)
+    }
+}
+
+/// What the transport buttons send unless the mapping says otherwise.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    /// Whatever [buttons] has for them, RPNs in the shipped mapping.
+    #[default]
+    Rpn,
+    /// MMC, in place of what [buttons] has for them.
+    Mmc,
+}
+
+const MMC_TRANSPORT: [(MaschineButton, MmcCommand); 6] = [
+    (MaschineButton::Play, MmcCommand::Play),
+    (MaschineButton::Erase, MmcCommand::Stop),
+    (MaschineButton::Rec, MmcCommand::RecordStrobe),
+    (MaschineButton::Restart, MmcCommand::Locate),
+    (MaschineButton::Stepleft, MmcCommand::Rewind),
+    (MaschineButton::Stepright, MmcCommand::FastForward),
+];
+
+/// How presses turn a button on and off.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Behavior {
+    /// On while it's held down.
+    #[default]
+    Momentary,
+    /// Every press flips it.
+    Toggle,
+    /// A press turns it on and every other button in its group off.
+    Radio,
+}
+
+/// A button's action and how it's played.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ButtonMapping {
+    #[serde(flatten)]
+    pub action: ButtonAction,
+    #[serde(default)]
+    pub behavior: Behavior,
+    /// Which radio group the button is in, the buttons without one share a group.
+    #[serde(default)]
+    pub group: String,
+}
+
+/// What a button sends. Channels are 1-16, like in profile.toml.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ButtonAction {
+    Cc {
+        #[serde(default = "default_channel")]
+        channel: u8,
+        number: U7,
+        #[serde(default = "default_on")]
+        on: U7,
+        #[serde(default)]
+        off: U7,
+    },
+    Note {
+        #[serde(default = "default_channel")]
+        channel: u8,
+        number: U7,
+        #[serde(default = "default_on")]
+        velocity: U7,
+    },
+    Program {
+        #[serde(default = "default_channel")]
+        channel: u8,
+        number: U7,
+    },
+    Rpn {
+        #[serde(default = "default_channel")]
+        channel: u8,
+        number: U14,
+        #[serde(default = "default_on")]
+        on: U7,
+        #[serde(default)]
+        off: U7,
+    },
+    Osc {
+        path: String,
+    },
+    Mmc {
+        command: MmcCommand,
+        /// 127 reaches every device.
+        #[serde(default = "default_mmc_device")]
+        device: U7,
+        #[serde(default)]
+        time: [U7; 4],
+    },
+    NoteBase {
+        note: U7,
+    },
+    Action {
+        action: InternalAction,
+    },
+    /// Runs the steps of a macro from [macros] when on.
+    Macro {
+        name: String,
+    },
+}
+
+fn default_channel() -> u8 {
+    1
+}
+
+fn default_on() -> U7 {
+    127
+}
+
+fn default_mmc_device() -> U7 {
+    0x7F
+}
+
+impl ButtonAction {
+    /// The MIDI for the button turning on or off, nothing for the types that don't send MIDI.
+    pub fn msgs(&self, on: bool) -> Vec<Message> {
+        let value = |on_value: U7, off_value: U7| if on { on_value } else { off_value };
+
+        match *self {
+            ButtonAction::Cc { channel, number, on: on_value, off } => {
+                vec![Message::ControlChange(midi_channel(channel), number, value(on_value, off))]
+            }
+
+            ButtonAction::Note { channel, number, velocity } if on => {
+                vec![Message::NoteOn(midi_channel(channel), number, velocity)]
+            }
+            ButtonAction::Note { channel, number, .. } => vec![Message::NoteOff(midi_channel(channel), number, 0)],
+
+            ButtonAction::Program { channel, number } if on => {
+                vec![Message::ProgramChange(midi_channel(channel), number)]
+            }
+
+            ButtonAction::Rpn { channel, number, on: on_value, off } => {
+                vec![Message::RPN7(midi_channel(channel), number, value(on_value, off))]
+            }
+
+            ButtonAction::Mmc { command, device, time } if on => vec![command.sysex(device, time)],
+
+            _ => vec![],
+        }
+    }
+
+    /// Internal actions light their buttons themselves.
+    pub fn lights_button(&self) -> bool {
+        !matches!(*self, ButtonAction::Action { .. })
+    }
+}
+
+/// The incoming MIDI that lights a control, on the Feedback MIDI port.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedbackSource {
+    /// The velocity is the value, a note off is 0.
+    Note {
+        #[serde(default = "default_channel")]
+        channel: u8,
+        number: U7,
+    },
+    Cc {
+        #[serde(default = "default_channel")]
+        channel: u8,
+        number: U7,
+    },
+}
+
+impl FeedbackSource {
+    /// The value `msg` sets the control to, if `msg` is this source with its channel moved
+    /// up by `channel_offset`.
+    fn value(&self, msg: &Message, channel_offset: u8) -> Option<U7> {
+        let is = |channel: u8, msg_channel: Channel| midi_channel(channel.saturating_add(channel_offset)) == msg_channel;
+
+        match (self, msg) {
+            (&FeedbackSource::Note { channel, number }, &Message::NoteOn(msg_channel, note, velocity))
+                if is(channel, msg_channel) && note == number =>
+            {
+                Some(velocity)
+            }
+            (&FeedbackSource::Note { channel, number }, &Message::NoteOff(msg_channel, note, _))
+                if is(channel, msg_channel) && note == number =>
+            {
+                Some(0)
+            }
+            (&FeedbackSource::Cc { channel, number }, &Message::ControlChange(msg_channel, cc, value))
+                if is(channel, msg_channel) && cc == number =>
+            {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// How the incoming value lights the control.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackLight {
+    /// The value is the brightness.
+    #[default]
+    Brightness,
+    /// The value picks the hue, 0 is off.
+    Hue,
+    /// The value picks a color of the Launchpad palette, 0 is off. Like on a Launchpad, the
+    /// source's channel lights it, the channel after flashes it against what it was lit with
+    /// and the one after that pulses it.
+    Palette,
+}
+
+// the Launchpad's channels for each animation, in order from the source's own
+const PALETTE_ANIMATIONS: [Animation; 3] = [Animation::Static, Animation::Flash, Animation::Pulse];
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Feedback {
+    #[serde(flatten)]
+    pub source: FeedbackSource,
+    #[serde(default)]
+    pub light: FeedbackLight,
+    /// 0xRRGGBB, the pad color when left out.
+    #[serde(default)]
+    pub color: Option<u32>,
+}
+
+impl Feedback {
+    /// The value `msg` sets the control to and how it's animated, if `msg` is for it.
+    pub fn value(&self, msg: &Message) -> Option<(U7, Animation)> {
+        let animations = match self.light {
+            FeedbackLight::Palette => &PALETTE_ANIMATIONS[..],
+            _ => &PALETTE_ANIMATIONS[..1],
+        };
+
+        animations
+            .iter()
+            .enumerate()
+            .find_map(|(channel_offset, &animation)| self.source.value(msg, channel_offset as u8).map(|value| (value, animation)))
+    }
+
+    /// The color and brightness for a value.
+    pub fn light(&self, value: U7, pad_color: u32) -> (u32, f32) {
+        let color = self.color.unwrap_or(pad_color);
+
+        match self.light {
+            FeedbackLight::Brightness => (color, value as f32 / 127.0),
+
+            FeedbackLight::Hue if value == 0 => (color, 0.0),
+            FeedbackLight::Hue => {
+                let (r, g, b) = HSL { h: value as f64 / 128.0 * 360.0, s: 1.0, l: 0.5 }.to_rgb();
+                (((r as u32) << 16) | ((g as u32) << 8) | (b as u32), 1.0)
+            }
+
+            FeedbackLight::Palette => (PALETTE[value as usize], if value == 0 { 0.0 } else { 1.0 }),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct FeedbackFile {
+    #[serde(default)]
+    pads: BTreeMap<String, Feedback>,
+    #[serde(default)]
+    buttons: BTreeMap<String, Feedback>,
+}
+
+/// Which set of mappings a control goes through, the shift layer while Shift is held.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Plain,
+    Shift,
+}
+
+#[derive(Deserialize)]
+struct MappingFile {
+    #[serde(default)]
+    transport: Transport,
+    #[serde(default)]
+    buttons: BTreeMap<String, ButtonMapping>,
+    #[serde(default)]
+    shift: ShiftFile,
+    #[serde(default)]
+    gestures: GestureTiming,
+    #[serde(default)]
+    long_press: BTreeMap<String, ButtonAction>,
+    #[serde(default)]
+    double_tap: BTreeMap<String, ButtonAction>,
+    #[serde(default)]
+    combos: BTreeMap<String, ButtonAction>,
+    #[serde(default)]
+    feedback: FeedbackFile,
+    #[serde(default)]
+    session: SessionConfig,
+    #[serde(default)]
+    macros: BTreeMap<String, Vec<MacroStep>>,
+}
+
+#[derive(Default, Deserialize)]
+struct ShiftFile {
+    #[serde(default)]
+    buttons: BTreeMap<String, ButtonMapping>,
+    #[serde(default)]
+    pads: BTreeMap<String, ButtonAction>,
+    #[serde(default)]
+    knobs: Vec<KnobConfig>,
+}
+
+/// What every button does, see mapping.toml, and which buttons are on.
+pub struct Mapping {
+    buttons: BTreeMap<(Layer, MaschineButton), ButtonMapping>,
+    on: BTreeMap<(Layer, MaschineButton), bool>,
+
+    // pads are momentary, without a shifted mapping they stay with the pad mode
+    shift_pads: BTreeMap<usize, ButtonAction>,
+    /// The knobs while Shift is held, the ones it leaves out are in fine mode.
+    pub shift_knobs: Option<KnobPage>,
+
+    pub gesture_timing: GestureTiming,
+    gestures: BTreeMap<Gesture, ButtonAction>,
+
+    feedback: Vec<(Control, Feedback)>,
+
+    /// The clip grid the session pad mode launches.
+    pub session: SessionConfig,
+
+    macros: BTreeMap<String, Vec<MacroStep>>,
+}
+
+impl Mapping {
+    /// A missing mapping falls back to the built-in one, a broken one is an error.
+    pub fn load(path: &Path) -> Mapping {
+        let mapping = match fs::read_to_string(path) {
+            Ok(mapping) => mapping,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => {
+                println!(" :: no {}, using the defaults", path.display());
+                DEFAULT_MAPPING.to_string()
+            }
+            Err(err) => panic!("couldn't read {}: {}", path.display(), err),
+        };
+
+        Mapping::parse(&mapping, path)
+    }
+
+    /// `path` is only for the errors.
+    fn parse(mapping: &str, path: &Path) -> Mapping {
+        let file: MappingFile = match toml::from_str(mapping) {
+            Ok(file) => file,
+            Err(err) => panic!("couldn't parse {}: {}", path.display(), err),
+        };
+
+        let layer_buttons = |layer: Layer, buttons: BTreeMap<String, ButtonMapping>| {
+            buttons
+                .into_iter()
+                .map(move |(name, mapping)| match osc_button_to_btn_map(&name) {
+                    Some(btn) => ((layer, btn), mapping),
+                    None => panic!("unknown button {} in {}", name, path.display()),
+                })
+        };
+
+        let mut buttons: BTreeMap<_, _> = layer_buttons(Layer::Plain, file.buttons)
+            .chain(layer_buttons(Layer::Shift, file.shift.buttons))
+            .collect();
+
+        if file.transport == Transport::Mmc {
+            for &(btn, command) in &MMC_TRANSPORT {
+                let mapping = ButtonMapping {
+                    action: ButtonAction::Mmc { command, device: default_mmc_device(), time: [0; 4] },
+                    behavior: Behavior::Momentary,
+                    group: String::new(),
+                };
+
+                buttons.insert((Layer::Plain, btn), mapping);
+            }
+        }
+
+        let shift_pads: BTreeMap<_, _> = file
+            .shift
+            .pads
+            .into_iter()
+            .map(|(name, action)| match name.parse() {
+                Ok(pad_idx @ 0..=15) => (pad_idx, action),
+                _ => panic!("unknown pad {} in {}", name, path.display()),
+            })
+            .collect();
+
+        for knob in &file.shift.knobs {
+            knob.check(path);
+        }
+
+        let shift_knobs = if file.shift.knobs.is_empty() {
+            None
+        } else {
+            let count = file.shift.knobs.len();
+            let mut page = KnobPage::from(&KnobPageConfig {
+                name: "Shift".to_string(),
+                knobs: file.shift.knobs,
+            });
+
+            // the default CCs it pads the page with would leave no knob to fine tune
+            page.knobs.truncate(count);
+            Some(page)
+        };
+
+        let button = |name: &str| match osc_button_to_btn_map(name) {
+            Some(btn) => btn,
+            None => panic!("unknown button {} in {}", name, path.display()),
+        };
+
+        let combo = |name: &str| {
+            let controls = name.split_once('+').and_then(|(held, pressed)| {
+                Some((osc_button_to_btn_map(held)?, Control::from_name(pressed)?))
+            });
+
+            match controls {
+                Some((held, pressed)) => Gesture::Combo(held, pressed),
+                None => panic!("unknown combo {} in {}", name, path.display()),
+            }
+        };
+
+        let gestures: BTreeMap<_, _> = (file.long_press.into_iter())
+            .map(|(name, action)| (Gesture::LongPress(button(&name)), action))
+            .chain(file.double_tap.into_iter().map(|(name, action)| (Gesture::DoubleTap(button(&name)), action)))
+            .chain(file.combos.into_iter().map(|(name, action)| (combo(&name), action)))
+            .collect();
+
+        let feedback = (file.feedback.pads.into_iter())
+            .map(|(name, feedback)| match name.parse() {
+                Ok(pad_idx @ 0..=15) => (Control::Pad(pad_idx), feedback),
+                _ => panic!("unknown pad {} in {}", name, path.display()),
+            })
+            .chain(file.feedback.buttons.into_iter().map(|(name, feedback)| (Control::Button(button(&name)), feedback)))
+            .collect();
+
+        if let Some((notes, other_notes)) = file.session.overlap() {
+            panic!("session {} and {} notes overlap in {}", notes, other_notes, path.display());
+        }
+
+        // a macro started from a macro could start itself forever
+        for (name, steps) in &file.macros {
+            if steps.iter().any(|step| matches!(*step, MacroStep::Action(ButtonAction::Macro { .. }))) {
+                panic!("macro {} starts a macro in {}", name, path.display());
+            }
+        }
+
+        let actions = (buttons.values().map(|mapping| &mapping.action))
+            .chain(shift_pads.values())
+            .chain(gestures.values());
+
+        for action in actions {
+            if let ButtonAction::Macro { ref name } = *action {
+                if !file.macros.contains_key(name) {
+                    panic!("unknown macro {} in {}", name, path.display());
+                }
+            }
+        }
+
+        Mapping {
+            buttons,
+            on: BTreeMap::new(),
+
+            shift_pads,
+            shift_knobs,
+
+            gesture_timing: file.gestures,
+            gestures,
+
+            feedback,
+            session: file.session,
+            macros: file.macros,
+        }
+    }
+
+    pub fn button(&self, layer: Layer, btn: MaschineButton) -> Option<&ButtonMapping> {
+        self.buttons.get(&(layer, btn))
+    }
+
+    pub fn is_on(&self, layer: Layer, btn: MaschineButton) -> bool {
+        self.on.get(&(layer, btn)).cloned().unwrap_or(false)
+    }
+
+    /// The buttons that do something else while Shift is held.
+    pub fn shifted_buttons(&self) -> Vec<MaschineButton> {
+        self.buttons
+            .keys()
+            .filter(|&&(layer, _)| layer == Layer::Shift)
+            .map(|&(_, btn)| btn)
+            .collect()
+    }
+
+    pub fn shift_pad(&self, pad_idx: usize) -> Option<&ButtonAction> {
+        self.shift_pads.get(&pad_idx)
+    }
+
+    pub fn gesture(&self, gesture: Gesture) -> Option<&ButtonAction> {
+        self.gestures.get(&gesture)
+    }
+
+    pub fn macro_steps(&self, name: &str) -> Option<&[MacroStep]> {
+        self.macros.get(name).map(Vec::as_slice)
+    }
+
+    /// The controls an incoming message lights and the value and animation it lights each with.
+    pub fn feedback(&self, msg: &Message) -> Vec<(Control, &Feedback, U7, Animation)> {
+        self.feedback
+            .iter()
+            .filter_map(|&(control, ref feedback)| {
+                let (value, animation) = feedback.value(msg)?;
+                Some((control, feedback, value, animation))
+            })
+            .collect()
+    }
+
+    /// Works out which buttons turn on or off for a press or release of `btn`, `btn` itself
+    /// first. Presses with Shift held go to the shift layer if the button has a mapping there.
+    /// A release only turns off a momentary button whose press turned it on, in either layer.
+    pub fn transitions(&mut self, btn: MaschineButton, is_down: bool, shift: bool) -> Vec<(Layer, MaschineButton, bool)> {
+        if !is_down {
+            let transitions: Vec<_> = [Layer::Plain, Layer::Shift]
+                .iter()
+                .filter(|&&layer| match self.button(layer, btn) {
+                    Some(mapping) => mapping.behavior == Behavior::Momentary && self.is_on(layer, btn),
+                    None => false,
+                })
+                .map(|&layer| (layer, btn, false))
+                .collect();
+
+            for &(layer, btn, on) in &transitions {
+                self.on.insert((layer, btn), on);
+            }
+
+            return transitions;
+        }
+
+        let layer = if shift && self.button(Layer::Shift, btn).is_some() {
+            Layer::Shift
+        } else {
+            Layer::Plain
+        };
+
+        let mapping = match self.button(layer, btn) {
+            Some(mapping) => mapping,
+            None => return vec![],
+        };
+
+        let transitions = match mapping.behavior {
+            Behavior::Momentary => vec![(layer, btn, true)],
+            Behavior::Toggle => vec![(layer, btn, !self.is_on(layer, btn))],
+
+            Behavior::Radio => {
+                let others = self.buttons.iter().filter(|&(&(other_layer, other), other_mapping)| {
+                    (other_layer, other) != (layer, btn)
+                        && other_layer == layer
+                        && other_mapping.behavior == Behavior::Radio
+                        && other_mapping.group == mapping.group
+                        && self.is_on(other_layer, other)
+                });
+
+                Some((layer, btn, true))
+                    .into_iter()
+                    .chain(others.map(|(&(other_layer, other), _)| (other_layer, other, false)))
+                    .collect()
+            }
+        };
+
+        for &(layer, btn, on) in &transitions {
+            self.on.insert((layer, btn), on);
+        }
+
+        transitions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use midi::Channel::{Ch1, Ch16, Ch2, Ch3, Ch4};
+
+    use base::MaschineButton::{F1, F2, F3, F4, F5};
+
+    fn mapping(mapping: &str) -> Mapping {
+        Mapping::parse(mapping, Path::new("test.toml"))
+    }
+
+    const BEHAVIORS: &str = r#"
+        [buttons]
+        f1 = { type = "cc", number = 1 }
+        f2 = { type = "cc", number = 2, behavior = "toggle" }
+        f3 = { type = "cc", number = 3, behavior = "radio", group = "scenes" }
+        f4 = { type = "cc", number = 4, behavior = "radio", group = "scenes" }
+        f5 = { type = "cc", number = 5, behavior = "radio" }
+
+        [shift.buttons]
+        f1 = { type = "cc", number = 11 }
+    "#;
+
+    #[test]
+    fn momentary() {
+        let mut mapping = mapping(BEHAVIORS);
+
+        assert_eq!(mapping.transitions(F1, true, false), vec![(Layer::Plain, F1, true)]);
+        assert!(mapping.is_on(Layer::Plain, F1));
+        assert_eq!(mapping.transitions(F1, false, false), vec![(Layer::Plain, F1, false)]);
+        assert!(!mapping.is_on(Layer::Plain, F1));
+
+        // nothing to let go of
+        assert_eq!(mapping.transitions(F1, false, false), vec![]);
+    }
+
+    #[test]
+    fn momentary_release_in_the_shift_layer() {
+        let mut mapping = mapping(BEHAVIORS);
+
+        assert_eq!(mapping.transitions(F1, true, true), vec![(Layer::Shift, F1, true)]);
+
+        // Shift let go first, the release still turns off what the press turned on
+        assert_eq!(mapping.transitions(F1, false, false), vec![(Layer::Shift, F1, false)]);
+        assert!(!mapping.is_on(Layer::Shift, F1));
+        assert!(!mapping.is_on(Layer::Plain, F1));
+    }
+
+    #[test]
+    fn toggle() {
+        let mut mapping = mapping(BEHAVIORS);
+
+        assert_eq!(mapping.transitions(F2, true, false), vec![(Layer::Plain, F2, true)]);
+        assert_eq!(mapping.transitions(F2, false, false), vec![]);
+        assert!(mapping.is_on(Layer::Plain, F2));
+
+        assert_eq!(mapping.transitions(F2, true, false), vec![(Layer::Plain, F2, false)]);
+        assert_eq!(mapping.transitions(F2, false, false), vec![]);
+        assert!(!mapping.is_on(Layer::Plain, F2));
+    }
+
+    #[test]
+    fn radio() {
+        let mut mapping = mapping(BEHAVIORS);
+
+        assert_eq!(mapping.transitions(F3, true, false), vec![(Layer::Plain, F3, true)]);
+        assert_eq!(mapping.transitions(F3, false, false), vec![]);
+
+        assert_eq!(
+            mapping.transitions(F4, true, false),
+            vec![(Layer::Plain, F4, true), (Layer::Plain, F3, false)]
+        );
+        assert!(!mapping.is_on(Layer::Plain, F3));
+
+        // another group leaves this one alone
+        assert_eq!(mapping.transitions(F5, true, false), vec![(Layer::Plain, F5, true)]);
+        assert!(mapping.is_on(Layer::Plain, F4));
+
+        // pressing the one that's on keeps it on
+        assert_eq!(mapping.transitions(F4, true, false), vec![(Layer::Plain, F4, true)]);
+    }
+
+    #[test]
+    fn shift_layer() {
+        let mut mapping = mapping(BEHAVIORS);
+
+        // F1 has a shifted mapping, F2 falls back to its plain one
+        assert_eq!(mapping.transitions(F1, true, true), vec![(Layer::Shift, F1, true)]);
+        assert!(!mapping.is_on(Layer::Plain, F1));
+        assert_eq!(mapping.transitions(F2, true, true), vec![(Layer::Plain, F2, true)]);
+
+        assert_eq!(mapping.shifted_buttons(), vec![F1]);
+        assert_eq!(mapping.button(Layer::Shift, F2).map(|mapping| mapping.behavior), None);
+    }
+
+    #[test]
+    fn shift_knobs() {
+        let mapping = mapping(
+            r#"
+            [shift]
+            knobs = [{ cc = 80 }, { cc = 81 }]
+        "#,
+        );
+
+        // only the knobs it lists, the rest keep fine mode
+        let knobs = mapping.shift_knobs.unwrap().knobs;
+        assert_eq!(knobs.iter().map(|knob| knob.cc).collect::<Vec<_>>(), vec![80, 81]);
+    }
+
+    fn mmc(data: Vec<U7>) -> Message {
+        Message::SysEx(Manufacturer::OneByte(0x7F), data)
+    }
+
+    #[test]
+    fn mmc_commands() {
+        assert_eq!(MmcCommand::Stop.sysex(127, [0; 4]), mmc(vec![127, 0x06, 0x01]));
+        assert_eq!(MmcCommand::Play.sysex(127, [0; 4]), mmc(vec![127, 0x06, 0x02]));
+        assert_eq!(MmcCommand::RecordStrobe.sysex(127, [0; 4]), mmc(vec![127, 0x06, 0x06]));
+        assert_eq!(MmcCommand::Pause.sysex(3, [0; 4]), mmc(vec![3, 0x06, 0x09]));
+    }
+
+    #[test]
+    fn mmc_locate() {
+        assert_eq!(
+            MmcCommand::Locate.sysex(127, [1, 2, 3, 4]),
+            mmc(vec![127, 0x06, 0x44, 0x06, 0x01, 1, 2, 3, 4, 0])
+        );
+    }
+
+    fn feedback(source: FeedbackSource, light: FeedbackLight) -> Feedback {
+        Feedback { source, light, color: None }
+    }
+
+    #[test]
+    fn feedback_source() {
+        let note = feedback(FeedbackSource::Note { channel: 2, number: 36 }, FeedbackLight::Brightness);
+
+        assert_eq!(note.value(&Message::NoteOn(Ch2, 36, 100)), Some((100, Animation::Static)));
+        assert_eq!(note.value(&Message::NoteOff(Ch2, 36, 64)), Some((0, Animation::Static)));
+        assert_eq!(note.value(&Message::NoteOn(Ch1, 36, 100)), None);
+        assert_eq!(note.value(&Message::NoteOn(Ch2, 37, 100)), None);
+        assert_eq!(note.value(&Message::ControlChange(Ch2, 36, 100)), None);
+
+        let cc = feedback(FeedbackSource::Cc { channel: 1, number: 20 }, FeedbackLight::Brightness);
+
+        assert_eq!(cc.value(&Message::ControlChange(Ch1, 20, 5)), Some((5, Animation::Static)));
+        assert_eq!(cc.value(&Message::ControlChange(Ch2, 20, 5)), None);
+    }
+
+    #[test]
+    fn palette_channel_offsets() {
+        let palette = feedback(FeedbackSource::Note { channel: 1, number: 11 }, FeedbackLight::Palette);
+
+        assert_eq!(palette.value(&Message::NoteOn(Ch1, 11, 5)), Some((5, Animation::Static)));
+        assert_eq!(palette.value(&Message::NoteOn(Ch2, 11, 5)), Some((5, Animation::Flash)));
+        assert_eq!(palette.value(&Message::NoteOn(Ch3, 11, 5)), Some((5, Animation::Pulse)));
+        assert_eq!(palette.value(&Message::NoteOn(Ch4, 11, 5)), None);
+
+        // only the palette animates
+        let brightness = feedback(FeedbackSource::Note { channel: 1, number: 11 }, FeedbackLight::Brightness);
+
+        assert_eq!(brightness.value(&Message::NoteOn(Ch2, 11, 5)), None);
+    }
+
+    #[test]
+    fn palette_offsets_stop_at_channel_16() {
+        let palette = feedback(FeedbackSource::Cc { channel: 16, number: 11 }, FeedbackLight::Palette);
+
+        assert_eq!(palette.value(&Message::ControlChange(Ch16, 11, 5)), Some((5, Animation::Static)));
+        assert_eq!(palette.value(&Message::ControlChange(Ch1, 11, 5)), None);
+    }
+
+    #[test]
+    fn palette_light() {
+        let palette = feedback(FeedbackSource::Note { channel: 1, number: 11 }, FeedbackLight::Palette);
+
+        assert_eq!(palette.light(5, 0xFFFFFF), (0xFF0000, 1.0));
+        assert_eq!(palette.light(0, 0xFFFFFF), (0x000000, 0.0));
+    }
+}
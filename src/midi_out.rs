@@ -0,0 +1,17 @@
+use alsa_seq::{SequencerHandle, SequencerPort};
+use midi::Message;
+
+/// The ALSA port everything MIDI goes out of. Cheap to copy, so it can be handed to the pad
+/// modes while the handler itself is borrowed.
+#[derive(Copy, Clone)]
+pub struct MidiOut<'a> {
+    pub seq_handle: &'a SequencerHandle,
+    pub seq_port: &'a SequencerPort<'a>,
+}
+
+impl<'a> MidiOut<'a> {
+    pub fn send(&self, msg: &Message) {
+        self.seq_port.send_message(msg).unwrap();
+        self.seq_handle.drain_output();
+    }
+}
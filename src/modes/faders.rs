@@ -0,0 +1,83 @@
+use midi::*;
+
+use base::Maschine;
+use modes::{ModeContext, PadModeHandler};
+use utils::{PadControl, PadRelease, PAD_CC_BASE, PAD_NOTE_MAP, PAD_RELEASED_BRIGHTNESS};
+
+/// Pad pressure drives a CC (or NRPN) per pad instead of playing a note.
+pub struct FadersMode {
+    pub controls: [PadControl; 16],
+    pub release: PadRelease,
+    values: [U14; 16],
+}
+
+impl FadersMode {
+    pub fn new() -> Self {
+        let mut controls = [PadControl::Cc(PAD_CC_BASE); 16];
+        for (pad_idx, control) in controls.iter_mut().enumerate() {
+            *control = PadControl::Cc(PAD_CC_BASE + PAD_NOTE_MAP[pad_idx]);
+        }
+
+        FadersMode {
+            controls,
+            release: PadRelease::SnapBack,
+            values: [0; 16],
+        }
+    }
+
+    fn fader(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32, new_press: bool) {
+        let value = (pressure.min(1.0) * 16383.0) as U14;
+
+        if self.release == PadRelease::Hold && !new_press && value <= self.values[pad_idx] {
+            return;
+        }
+
+        self.set_value(ctx, maschine, pad_idx, value);
+    }
+
+    fn set_value(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, value: U14) {
+        let prev = self.values[pad_idx];
+        self.values[pad_idx] = value;
+
+        // the pressure stream is much finer than 7 bits, don't repeat ourselves on the wire
+        let msg = match self.controls[pad_idx] {
+            PadControl::Cc(cc) if (value >> 7) != (prev >> 7) => {
+                Some(Message::RPN7(Ch1, cc as U14, (value >> 7) as U7))
+            }
+            PadControl::Nrpn(nrpn) if value != prev => Some(Message::NRPN14(Ch1, nrpn, value)),
+            _ => None,
+        };
+
+        if let Some(msg) = msg {
+            ctx.send_midi_msg(&msg);
+        }
+
+        maschine.set_pad_light(pad_idx, ctx.color, self.brightness(pad_idx));
+    }
+
+    fn brightness(&self, pad_idx: usize) -> f32 {
+        (self.values[pad_idx] as f32 / 16383.0).max(PAD_RELEASED_BRIGHTNESS)
+    }
+}
+
+impl PadModeHandler for FadersMode {
+    fn pad_pressed(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {
+        self.fader(ctx, maschine, pad_idx, pressure, true);
+    }
+
+    fn pad_aftertouch(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {
+        self.fader(ctx, maschine, pad_idx, pressure, false);
+    }
+
+    fn pad_released(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize) {
+        if self.release == PadRelease::SnapBack {
+            self.set_value(ctx, maschine, pad_idx, 0);
+        }
+    }
+
+    fn render_lights(&self, ctx: &ModeContext, maschine: &mut dyn Maschine) {
+        for pad_idx in 0..16 {
+            maschine.set_pad_light(pad_idx, ctx.color, self.brightness(pad_idx));
+        }
+    }
+}
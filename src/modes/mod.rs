@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+
+use midi::{Channel, Message, U7};
+
+use base::{Maschine, MaschineButton};
+use knobs::KnobPage;
+use midi_out::MidiOut;
+use utils::PressureShape;
+
+mod faders;
+mod mpe;
+mod notes;
+mod program;
+mod sequencer;
+mod session;
+mod snapshots;
+
+pub use self::faders::FadersMode;
+pub use self::mpe::MpeMode;
+pub use self::notes::NotesMode;
+pub use self::program::ProgramMode;
+pub use self::sequencer::SequencerMode;
+pub use self::session::{SessionConfig, SessionMode};
+pub use self::snapshots::{Snapshot, SnapshotsMode};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PadMode {
+    Notes,
+    Faders,
+    Sequencer,
+    Program,
+    Mpe,
+    Snapshots,
+    Session,
+}
+
+impl PadMode {
+    pub const ALL: [PadMode; 7] = [
+        PadMode::Notes,
+        PadMode::Faders,
+        PadMode::Sequencer,
+        PadMode::Program,
+        PadMode::Mpe,
+        PadMode::Snapshots,
+        PadMode::Session,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PadMode::Notes => "notes",
+            PadMode::Faders => "faders",
+            PadMode::Sequencer => "sequencer",
+            PadMode::Program => "program",
+            PadMode::Mpe => "mpe",
+            PadMode::Snapshots => "snapshots",
+            PadMode::Session => "session",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PadMode> {
+        PadMode::ALL.iter().cloned().find(|mode| mode.name() == name)
+    }
+
+    pub fn print_help(self) {
+        println!("Padmode {}", self.name());
+
+        match self {
+            PadMode::Notes => {}
+            PadMode::Faders => {
+                println!("This is Pad fader mode");
+                println!();
+                println!("Pad pressure is sent as a CC per pad instead of notes.");
+            }
+            PadMode::Sequencer => {
+                println!("This is Sequencer mode");
+                println!();
+                println!("Tapping on pads activates them for the sequence.");
+                println!("Tapping on a pad while holding shift, then pressing another pad");
+                println!("will change the note of the pad you pressed first");
+            }
+            PadMode::Program => {
+                println!("This is Program change mode");
+                println!();
+                println!("Pads send program changes, group buttons select the bank. The encoder");
+                println!("steps through the programs 16 at a time, Shift+encoder the banks 8 at");
+                println!("a time.");
+            }
+            PadMode::Mpe => {
+                println!("This is MPE mode");
+                println!();
+                println!("Every pad plays on its own MPE member channel, pad pressure is sent");
+                println!("as channel pressure and knob 1 sends slide (CC74).");
+            }
+            PadMode::Snapshots => {
+                println!("This is Snapshot mode");
+                println!();
+                println!("Tapping on a pad recalls the knob snapshot stored on it, tapping on");
+                println!("a pad while holding shift stores every knob of every page there.");
+                println!("Holding a pad and pressing another one lets the encoder morph");
+                println!("between the two snapshots.");
+            }
+            PadMode::Session => {
+                println!("This is Session mode");
+                println!();
+                println!("Pads launch the clips of 4 tracks by 4 scenes, nav left/right and");
+                println!("the encoder scroll the grid. Scene, Mute or Solo switch the pads to");
+                println!("launching their row's scene, or muting or soloing their track.");
+            }
+        }
+    }
+}
+
+/// What the handler lends a pad mode for the duration of a call.
+pub struct ModeContext<'a> {
+    pub midi: MidiOut<'a>,
+    pub color: u32,
+    pub pressure_shape: PressureShape,
+    pub send_aftertouch: bool,
+
+    pub knob_pages: &'a mut [KnobPage],
+    /// The last value each absolute CC was sent with, anything a mode sends for a knob goes in
+    /// here too so soft takeover knows about it.
+    pub parameter_values: &'a mut BTreeMap<(Channel, U7), U7>,
+}
+
+impl<'a> ModeContext<'a> {
+    pub fn send_midi_msg(&self, msg: &Message) {
+        self.midi.send(msg);
+    }
+
+    pub fn pressure_to_vel(&self, pressure: f32) -> U7 {
+        self.pressure_shape.velocity(pressure)
+    }
+}
+
+/// A pad mode owns what the pads do, what they look like, and may take over buttons and
+/// knobs while it is active.
+#[allow(unused_variables)]
+pub trait PadModeHandler {
+    fn enter(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine) {
+        self.render_lights(ctx, maschine);
+    }
+    fn leave(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine) {}
+
+    fn pad_pressed(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32);
+    fn pad_aftertouch(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {}
+    fn pad_released(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize) {}
+
+    fn render_lights(&self, ctx: &ModeContext, maschine: &mut dyn Maschine);
+
+    /// Returns true if the mode took the button press, in which case neither it nor the
+    /// release is sent out.
+    fn button_down(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, btn: MaschineButton) -> bool {
+        false
+    }
+
+    /// Same as `button_down`, for knob values.
+    fn knob_value(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, knob_idx: usize, value: U7) -> bool {
+        false
+    }
+
+    /// Same as `button_down`, for encoder steps.
+    fn encoder_step(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, delta: i32) -> bool {
+        false
+    }
+}
+
+/// Every pad mode, the ones Shift+Pad Mode cycles through, and which one is active.
+pub struct PadModes {
+    pub notes: NotesMode,
+    pub faders: FadersMode,
+    pub sequencer: SequencerMode,
+    pub program: ProgramMode,
+    pub mpe: MpeMode,
+    pub snapshots: SnapshotsMode,
+    pub session: SessionMode,
+
+    cycle: Vec<PadMode>,
+    current: PadMode,
+}
+
+impl PadModes {
+    pub fn new() -> Self {
+        PadModes {
+            notes: NotesMode::new(),
+            faders: FadersMode::new(),
+            sequencer: SequencerMode::new(),
+            program: ProgramMode::new(),
+            mpe: MpeMode::new(),
+            snapshots: SnapshotsMode::new(),
+            session: SessionMode::new(),
+
+            cycle: PadMode::ALL.to_vec(),
+            current: PadMode::Notes,
+        }
+    }
+
+    pub fn get_mut(&mut self, mode: PadMode) -> &mut dyn PadModeHandler {
+        match mode {
+            PadMode::Notes => &mut self.notes,
+            PadMode::Faders => &mut self.faders,
+            PadMode::Sequencer => &mut self.sequencer,
+            PadMode::Program => &mut self.program,
+            PadMode::Mpe => &mut self.mpe,
+            PadMode::Snapshots => &mut self.snapshots,
+            PadMode::Session => &mut self.session,
+        }
+    }
+
+    pub fn current(&self) -> PadMode {
+        self.current
+    }
+
+    pub fn current_mut(&mut self) -> &mut dyn PadModeHandler {
+        let current = self.current;
+        self.get_mut(current)
+    }
+
+    /// The modes are cycled through in the given order. The current mode stays active even if
+    /// it's left out, the next press of Shift+Pad Mode goes to the start of the cycle then.
+    pub fn set_cycle(&mut self, cycle: Vec<PadMode>) {
+        if !cycle.is_empty() {
+            self.cycle = cycle;
+        }
+    }
+
+    pub fn next_in_cycle(&self) -> PadMode {
+        match self.cycle.iter().position(|&mode| mode == self.current) {
+            Some(idx) => self.cycle[(idx + 1) % self.cycle.len()],
+            None => self.cycle[0],
+        }
+    }
+
+    pub fn switch_to(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, mode: PadMode) {
+        if mode == self.current {
+            return;
+        }
+
+        self.current_mut().leave(ctx, maschine);
+        self.current = mode;
+        self.current_mut().enter(ctx, maschine);
+
+        mode.print_help();
+    }
+}
@@ -0,0 +1,119 @@
+use midi::*;
+
+use base::Maschine;
+use modes::{ModeContext, PadModeHandler};
+use utils::{MPE_MEMBER_CHANNELS, MPE_SLIDE_CC, PAD_NOTE_MAP, PAD_RELEASED_BRIGHTNESS};
+
+/// Every sounding pad gets an MPE member channel of its own, so its pressure can go out as
+/// channel pressure.
+pub struct MpeMode {
+    pad_channels: [Option<usize>; 16],
+    pad_notes: [U7; 16],
+    next_channel: usize,
+    slide: U7,
+}
+
+impl MpeMode {
+    pub fn new() -> Self {
+        MpeMode {
+            pad_channels: [None; 16],
+            pad_notes: [0; 16],
+            next_channel: 0,
+            slide: 64,
+        }
+    }
+
+    fn allocate_channel(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine) -> usize {
+        let count = MPE_MEMBER_CHANNELS.len();
+
+        // round robin over the free channels, so a new note doesn't land on the release tail
+        // of the one that just ended
+        for offset in 0..count {
+            let member = (self.next_channel + offset) % count;
+
+            if !self.pad_channels.contains(&Some(member)) {
+                self.next_channel = (member + 1) % count;
+                return member;
+            }
+        }
+
+        // 16 pads but only 15 member channels, steal the next one in line
+        let member = self.next_channel;
+        self.next_channel = (member + 1) % count;
+
+        if let Some(pad_idx) = self.pad_channels.iter().position(|&c| c == Some(member)) {
+            self.pad_released(ctx, maschine, pad_idx);
+        }
+
+        member
+    }
+
+    fn set_slide(&mut self, ctx: &mut ModeContext, value: U7) {
+        self.slide = value;
+
+        for &member in self.pad_channels.iter().flatten() {
+            ctx.send_midi_msg(&Message::ControlChange(MPE_MEMBER_CHANNELS[member], MPE_SLIDE_CC, value));
+        }
+    }
+}
+
+impl PadModeHandler for MpeMode {
+    fn enter(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine) {
+        self.render_lights(ctx, maschine);
+    }
+
+    fn leave(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine) {
+        for pad_idx in 0..16 {
+            self.pad_released(ctx, maschine, pad_idx);
+        }
+    }
+
+    fn pad_pressed(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {
+        let member = self.allocate_channel(ctx, maschine);
+        let channel = MPE_MEMBER_CHANNELS[member];
+        let note = maschine.get_midi_note_base() + PAD_NOTE_MAP[pad_idx];
+
+        self.pad_channels[pad_idx] = Some(member);
+        self.pad_notes[pad_idx] = note;
+
+        // the other dimensions have to be in place before the note starts
+        ctx.send_midi_msg(&Message::ControlChange(channel, MPE_SLIDE_CC, self.slide));
+        ctx.send_midi_msg(&Message::ChannelPressure(channel, 0));
+        ctx.send_midi_msg(&Message::NoteOn(channel, note, ctx.pressure_to_vel(pressure)));
+
+        maschine.set_pad_light(pad_idx, ctx.color, pressure.sqrt());
+    }
+
+    fn pad_aftertouch(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {
+        if let Some(member) = self.pad_channels[pad_idx] {
+            let pressure_val = (pressure.min(1.0) * 127.0) as U7;
+            ctx.send_midi_msg(&Message::ChannelPressure(MPE_MEMBER_CHANNELS[member], pressure_val));
+
+            maschine.set_pad_light(pad_idx, ctx.color, pressure.sqrt());
+        }
+    }
+
+    fn pad_released(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize) {
+        if let Some(member) = self.pad_channels[pad_idx].take() {
+            let note = self.pad_notes[pad_idx];
+            ctx.send_midi_msg(&Message::NoteOff(MPE_MEMBER_CHANNELS[member], note, 0));
+        }
+
+        maschine.set_pad_light(pad_idx, ctx.color, PAD_RELEASED_BRIGHTNESS);
+    }
+
+    fn render_lights(&self, ctx: &ModeContext, maschine: &mut dyn Maschine) {
+        for pad_idx in 0..16 {
+            maschine.set_pad_light(pad_idx, ctx.color, PAD_RELEASED_BRIGHTNESS);
+        }
+    }
+
+    fn knob_value(&mut self, ctx: &mut ModeContext, _: &mut dyn Maschine, knob_idx: usize, value: U7) -> bool {
+        if knob_idx != 0 {
+            return false;
+        }
+
+        self.set_slide(ctx, value);
+        true
+    }
+}
@@ -0,0 +1,135 @@
+use midi::*;
+
+use base::Maschine;
+use modes::{ModeContext, PadModeHandler};
+use utils::{PressureShape, VelocityZone, PAD_NOTE_MAP, PAD_RELEASED_BRIGHTNESS};
+
+/// The default mode, pads play notes from the MIDI note base up.
+pub struct NotesMode {
+    zones: [Vec<VelocityZone>; 16],
+
+    // channel and note each pad was struck with, so aftertouch and note off follow the zone
+    // the strike picked even if the zones or the note base change while it's held
+    playing: [Option<(Channel, U7)>; 16],
+}
+
+impl NotesMode {
+    pub fn new() -> Self {
+        NotesMode {
+            zones: Default::default(),
+            playing: [None; 16],
+        }
+    }
+
+    /// Replaces the velocity zones of a pad, they've been through `check_velocity_zones`.
+    /// No zones at all makes the pad play its usual note on channel 1 again.
+    pub fn set_velocity_zones(&mut self, pad_idx: usize, mut zones: Vec<VelocityZone>) {
+        zones.sort_by_key(|zone| zone.min_velocity);
+        self.zones[pad_idx] = zones;
+    }
+
+    /// The hardest zone a strike reaches.
+    fn zone(&self, pad_idx: usize, velocity: U7) -> Option<&VelocityZone> {
+        self.zones[pad_idx].iter().rev().find(|zone| velocity >= zone.min_velocity)
+    }
+
+    fn zone_note(&self, maschine: &dyn Maschine, pad_idx: usize, velocity: U7) -> (Channel, U7) {
+        let pad_note = maschine.get_midi_note_base() + PAD_NOTE_MAP[pad_idx];
+
+        match self.zone(pad_idx, velocity) {
+            Some(zone) => (zone.channel, zone.note.unwrap_or(pad_note)),
+            None => (Ch1, pad_note),
+        }
+    }
+}
+
+impl PadModeHandler for NotesMode {
+    fn leave(&mut self, ctx: &mut ModeContext, _maschine: &mut dyn Maschine) {
+        for playing in self.playing.iter_mut() {
+            if let Some((channel, midi_note)) = playing.take() {
+                ctx.send_midi_msg(&Message::NoteOff(channel, midi_note, 0));
+            }
+        }
+    }
+
+    fn pad_pressed(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {
+        let velocity = ctx.pressure_to_vel(pressure);
+        let (channel, midi_note) = self.zone_note(maschine, pad_idx, velocity);
+
+        ctx.send_midi_msg(&Message::NoteOn(channel, midi_note, velocity));
+        maschine.set_pad_light(pad_idx, ctx.color, pressure.sqrt());
+
+        self.playing[pad_idx] = Some((channel, midi_note));
+    }
+
+    fn pad_aftertouch(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {
+        if let PressureShape::Constant(_) = ctx.pressure_shape {
+            return;
+        }
+
+        if !ctx.send_aftertouch {
+            return;
+        }
+
+        let (channel, midi_note) = match self.playing[pad_idx] {
+            Some(playing) => playing,
+            None => return,
+        };
+        let msg = Message::PolyphonicPressure(channel, midi_note, ctx.pressure_to_vel(pressure));
+
+        ctx.send_midi_msg(&msg);
+        maschine.set_pad_light(pad_idx, ctx.color, pressure.sqrt());
+    }
+
+    fn pad_released(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize) {
+        if let Some((channel, midi_note)) = self.playing[pad_idx].take() {
+            ctx.send_midi_msg(&Message::NoteOff(channel, midi_note, 0));
+        }
+
+        maschine.set_pad_light(pad_idx, ctx.color, PAD_RELEASED_BRIGHTNESS);
+    }
+
+    fn render_lights(&self, ctx: &ModeContext, maschine: &mut dyn Maschine) {
+        for pad_idx in 0..16 {
+            maschine.set_pad_light(pad_idx, ctx.color, PAD_RELEASED_BRIGHTNESS);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(min_velocity: U7, note: U7) -> VelocityZone {
+        VelocityZone { min_velocity, note: Some(note), channel: Ch10 }
+    }
+
+    fn zone_note(notes: &NotesMode, velocity: U7) -> Option<U7> {
+        notes.zone(0, velocity).and_then(|zone| zone.note)
+    }
+
+    #[test]
+    fn zone_boundaries() {
+        let mut notes = NotesMode::new();
+        notes.set_velocity_zones(0, vec![zone(100, 40), zone(1, 37), zone(64, 38)]);
+
+        assert_eq!(zone_note(&notes, 0), None);
+        assert_eq!(zone_note(&notes, 1), Some(37));
+        assert_eq!(zone_note(&notes, 63), Some(37));
+        assert_eq!(zone_note(&notes, 64), Some(38));
+        assert_eq!(zone_note(&notes, 99), Some(38));
+        assert_eq!(zone_note(&notes, 100), Some(40));
+        assert_eq!(zone_note(&notes, 127), Some(40));
+    }
+
+    #[test]
+    fn no_zones() {
+        let mut notes = NotesMode::new();
+        notes.set_velocity_zones(0, vec![zone(0, 36)]);
+
+        assert!(notes.zone(1, 127).is_none());
+
+        notes.set_velocity_zones(0, vec![]);
+        assert!(notes.zone(0, 127).is_none());
+    }
+}
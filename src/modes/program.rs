@@ -0,0 +1,144 @@
+use midi::*;
+
+use base::{Maschine, MaschineButton};
+use modes::{ModeContext, PadModeHandler};
+use utils::{
+    bank_select_msgs, BankSelect, BUTTON_LIGHT_OFF, BUTTON_LIGHT_ON, GROUP_BUTTONS, PAD_NOTE_MAP,
+    PAD_RELEASED_BRIGHTNESS,
+};
+
+// the encoder moves the pads a page of programs at a time, Shift+encoder the group buttons a
+// page of banks
+const PROGRAM_PAGE: usize = 16;
+const BANK_PAGE: usize = 8;
+
+/// Pads pick a program in the bank chosen with the group buttons. The encoder scrolls the
+/// pads through all 128 programs and Shift+encoder the group buttons through the banks.
+pub struct ProgramMode {
+    pub bank_select: BankSelect,
+    program_offset: usize,
+    bank_offset: usize,
+    bank: usize,
+    program: Option<(usize, U7)>,
+}
+
+impl ProgramMode {
+    pub fn new() -> Self {
+        ProgramMode {
+            bank_select: BankSelect::Msb,
+            program_offset: 0,
+            bank_offset: 0,
+            bank: 0,
+            program: None,
+        }
+    }
+
+    /// The highest bank the Bank Select controllers can send, 14 bits with both of them.
+    fn max_bank(&self) -> usize {
+        match self.bank_select {
+            BankSelect::MsbLsb => 0x3FFF,
+            _ => 0x7F,
+        }
+    }
+
+    fn scroll(offset: usize, delta: i32, page: usize, max: usize) -> usize {
+        let last_page = (max + 1 - page) as i32;
+        (offset as i32 + delta * page as i32).clamp(0, last_page) as usize
+    }
+
+    fn pad_program(&self, pad_idx: usize) -> U7 {
+        (self.program_offset + PAD_NOTE_MAP[pad_idx] as usize) as U7
+    }
+}
+
+impl PadModeHandler for ProgramMode {
+    fn leave(&mut self, _: &mut ModeContext, maschine: &mut dyn Maschine) {
+        for &btn in GROUP_BUTTONS.iter() {
+            maschine.set_button_light(btn, 0xFFFFFF, BUTTON_LIGHT_OFF);
+        }
+    }
+
+    fn pad_pressed(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, _: f32) {
+        let program = self.pad_program(pad_idx);
+
+        for msg in bank_select_msgs(Ch1, self.bank_select, self.bank as U14) {
+            ctx.send_midi_msg(&msg);
+        }
+        ctx.send_midi_msg(&Message::ProgramChange(Ch1, program));
+
+        self.program = Some((self.bank, program));
+        self.render_lights(ctx, maschine);
+    }
+
+    fn render_lights(&self, ctx: &ModeContext, maschine: &mut dyn Maschine) {
+        for pad_idx in 0..16 {
+            let brightness = if self.program == Some((self.bank, self.pad_program(pad_idx))) {
+                1.0
+            } else {
+                PAD_RELEASED_BRIGHTNESS
+            };
+
+            maschine.set_pad_light(pad_idx, ctx.color, brightness);
+        }
+
+        for (group, &btn) in GROUP_BUTTONS.iter().enumerate() {
+            let brightness = if self.bank_offset + group == self.bank { BUTTON_LIGHT_ON } else { BUTTON_LIGHT_OFF };
+            maschine.set_button_light(btn, 0xFFFFFF, brightness);
+        }
+    }
+
+    fn button_down(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, btn: MaschineButton) -> bool {
+        match GROUP_BUTTONS.iter().position(|&group| group == btn) {
+            Some(group) => {
+                self.bank = (self.bank_offset + group).min(self.max_bank());
+                self.render_lights(ctx, maschine);
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    fn encoder_step(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, delta: i32) -> bool {
+        if maschine.get_mod() == 1 {
+            self.bank_offset = Self::scroll(self.bank_offset, delta, BANK_PAGE, self.max_bank());
+        } else {
+            self.program_offset = Self::scroll(self.program_offset, delta, PROGRAM_PAGE, 127);
+        }
+
+        self.render_lights(ctx, maschine);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn program_pages() {
+        assert_eq!(ProgramMode::scroll(0, 1, PROGRAM_PAGE, 127), 16);
+        assert_eq!(ProgramMode::scroll(16, -2, PROGRAM_PAGE, 127), 0);
+        assert_eq!(ProgramMode::scroll(96, 5, PROGRAM_PAGE, 127), 112);
+    }
+
+    #[test]
+    fn bank_pages() {
+        let mut program = ProgramMode::new();
+
+        assert_eq!(ProgramMode::scroll(0, 100, BANK_PAGE, program.max_bank()), 120);
+
+        program.bank_select = BankSelect::MsbLsb;
+        assert_eq!(ProgramMode::scroll(0, 10000, BANK_PAGE, program.max_bank()), 16376);
+    }
+
+    #[test]
+    fn pad_programs() {
+        let mut program = ProgramMode::new();
+        program.program_offset = 112;
+
+        // the bottom left pad is the page's first program, the top right its last
+        assert_eq!(program.pad_program(12), 112);
+        assert_eq!(program.pad_program(3), 127);
+    }
+}
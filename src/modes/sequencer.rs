@@ -0,0 +1,53 @@
+use base::{Maschine, MaschineButton};
+use modes::{ModeContext, PadModeHandler};
+use utils::{PAD_NOTE_MAP, PAD_RELEASED_BRIGHTNESS};
+
+/// Pads toggle the steps of the internal sequencer. The steps themselves live on the device and
+/// are played back from `ev_loop`.
+pub struct SequencerMode;
+
+impl SequencerMode {
+    pub fn new() -> Self {
+        SequencerMode
+    }
+}
+
+impl PadModeHandler for SequencerMode {
+    fn pad_pressed(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, pressure: f32) {
+        if maschine.get_mod() == 1 {
+            let midi_note = maschine.get_midi_note_base() + PAD_NOTE_MAP[pad_idx];
+            maschine.note_save(pad_idx, midi_note, ctx.pressure_to_vel(pressure));
+        } else if maschine.note_check(pad_idx) == 0 {
+            maschine.note_state(pad_idx, 1);
+            maschine.set_pad_light(pad_idx, ctx.color, pressure.sqrt());
+        } else {
+            maschine.note_state(pad_idx, 0);
+            maschine.set_pad_light(pad_idx, ctx.color, PAD_RELEASED_BRIGHTNESS);
+        }
+    }
+
+    fn render_lights(&self, ctx: &ModeContext, maschine: &mut dyn Maschine) {
+        for pad_idx in 0..16 {
+            let brightness = if maschine.note_check(pad_idx) == 1 { 1.0 } else { PAD_RELEASED_BRIGHTNESS };
+            maschine.set_pad_light(pad_idx, ctx.color, brightness);
+        }
+    }
+
+    fn button_down(&mut self, _: &mut ModeContext, maschine: &mut dyn Maschine, btn: MaschineButton) -> bool {
+        match btn {
+            MaschineButton::Play => {
+                maschine.set_playing(1);
+                println!("playing notes");
+            }
+
+            MaschineButton::Erase => {
+                maschine.set_playing(0);
+                println!("stop");
+            }
+
+            _ => return false,
+        }
+
+        true
+    }
+}
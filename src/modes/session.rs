@@ -0,0 +1,464 @@
+use midi::*;
+
+use base::{Maschine, MaschineButton};
+use modes::{ModeContext, PadModeHandler};
+use utils::{midi_channel, BUTTON_LIGHT_OFF, BUTTON_LIGHT_ON, PAD_RELEASED_BRIGHTNESS};
+
+// the pads show this much of the DAW's grid at a time
+const GRID_TRACKS: usize = 4;
+const GRID_SCENES: usize = 4;
+
+/// The DAW's clip grid and the notes that launch it, the [session] table of mapping.toml.
+/// The clip of track t in scene s is note clip_note + s * tracks + t, scene s is scene_note + s
+/// and the mute and solo of track t are mute_note + t and solo_note + t, all on `channel`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SessionConfig {
+    #[serde(default = "default_channel")]
+    pub channel: u8,
+    #[serde(default = "default_size")]
+    pub tracks: usize,
+    #[serde(default = "default_size")]
+    pub scenes: usize,
+
+    #[serde(default)]
+    pub clip_note: U7,
+    #[serde(default = "default_scene_note")]
+    pub scene_note: U7,
+    #[serde(default = "default_mute_note")]
+    pub mute_note: U7,
+    #[serde(default = "default_solo_note")]
+    pub solo_note: U7,
+
+    #[serde(default)]
+    pub colors: ClipColors,
+}
+
+impl SessionConfig {
+    /// The first two kinds of note whose ranges overlap, they couldn't be told apart.
+    pub fn overlap(&self) -> Option<(&'static str, &'static str)> {
+        let ranges = [
+            ("clip", self.clip_note as usize, self.tracks * self.scenes),
+            ("scene", self.scene_note as usize, self.scenes),
+            ("mute", self.mute_note as usize, self.tracks),
+            ("solo", self.solo_note as usize, self.tracks),
+        ];
+
+        for (idx, &(name, start, len)) in ranges.iter().enumerate() {
+            for &(other_name, other_start, other_len) in &ranges[idx + 1..] {
+                if start < other_start + other_len && other_start < start + len {
+                    return Some((name, other_name));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn default_channel() -> u8 {
+    1
+}
+
+fn default_size() -> usize {
+    8
+}
+
+// right after the 8x8 clips
+fn default_scene_note() -> U7 {
+    64
+}
+
+fn default_mute_note() -> U7 {
+    72
+}
+
+fn default_solo_note() -> U7 {
+    80
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            channel: default_channel(),
+            tracks: default_size(),
+            scenes: default_size(),
+
+            clip_note: 0,
+            scene_note: default_scene_note(),
+            mute_note: default_mute_note(),
+            solo_note: default_solo_note(),
+
+            colors: ClipColors::default(),
+        }
+    }
+}
+
+/// 0xRRGGBB for each state a clip can be in.
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct ClipColors {
+    #[serde(default)]
+    pub empty: u32,
+    #[serde(default = "default_stopped_color")]
+    pub stopped: u32,
+    #[serde(default = "default_playing_color")]
+    pub playing: u32,
+    #[serde(default = "default_recording_color")]
+    pub recording: u32,
+}
+
+fn default_stopped_color() -> u32 {
+    0xFF8000
+}
+
+fn default_playing_color() -> u32 {
+    0x00FF00
+}
+
+fn default_recording_color() -> u32 {
+    0xFF0000
+}
+
+impl Default for ClipColors {
+    fn default() -> Self {
+        ClipColors {
+            empty: 0x000000,
+            stopped: default_stopped_color(),
+            playing: default_playing_color(),
+            recording: default_recording_color(),
+        }
+    }
+}
+
+/// What a clip is doing, the DAW sends it as the velocity of the clip's note.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ClipState {
+    Empty,
+    Stopped,
+    Playing,
+    Recording,
+}
+
+impl ClipState {
+    fn from_velocity(velocity: U7) -> Self {
+        match velocity {
+            0 => ClipState::Empty,
+            1 => ClipState::Stopped,
+            2 => ClipState::Playing,
+            _ => ClipState::Recording,
+        }
+    }
+}
+
+/// What pressing a pad does, Scene, Mute and Solo switch it on and off.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PadAction {
+    Clip,
+    Scene,
+    Mute,
+    Solo,
+}
+
+const PAD_ACTION_BUTTONS: [(MaschineButton, PadAction); 3] = [
+    (MaschineButton::Scene, PadAction::Scene),
+    (MaschineButton::Mute, PadAction::Mute),
+    (MaschineButton::Solo, PadAction::Solo),
+];
+
+/// The pads are a window of 4 tracks by 4 scenes on the DAW's clip grid, colored by what the
+/// DAW says each clip is doing. Nav left/right scroll the tracks and the encoder the scenes.
+/// With Scene on the pads launch their row's scene, with Mute or Solo on they mute or solo
+/// their column's track.
+pub struct SessionMode {
+    config: SessionConfig,
+
+    clips: Vec<ClipState>,
+    muted: Vec<bool>,
+    soloed: Vec<bool>,
+
+    track_offset: usize,
+    scene_offset: usize,
+    pad_action: PadAction,
+    // the note each held pad sent, for its note off
+    held: [Option<U7>; 16],
+}
+
+impl SessionMode {
+    pub fn new() -> Self {
+        let config = SessionConfig::default();
+
+        SessionMode {
+            clips: vec![ClipState::Empty; config.tracks * config.scenes],
+            muted: vec![false; config.tracks],
+            soloed: vec![false; config.tracks],
+            config,
+
+            track_offset: 0,
+            scene_offset: 0,
+            pad_action: PadAction::Clip,
+            held: [None; 16],
+        }
+    }
+
+    pub fn set_config(&mut self, config: SessionConfig) {
+        self.clips = vec![ClipState::Empty; config.tracks * config.scenes];
+        self.muted = vec![false; config.tracks];
+        self.soloed = vec![false; config.tracks];
+        self.track_offset = 0;
+        self.scene_offset = 0;
+        self.config = config;
+    }
+
+    // notes past 127 can't be sent, those clips, scenes and tracks are left out
+    fn note(base: U7, idx: usize) -> Option<U7> {
+        Some(base as usize + idx).filter(|&note| note <= 127).map(|note| note as U7)
+    }
+
+    /// The DAW's track and scene under a pad, if its grid reaches that far.
+    fn pad_clip(&self, pad_idx: usize) -> Option<(usize, usize)> {
+        let track = self.track_offset + pad_idx % GRID_TRACKS;
+        let scene = self.scene_offset + pad_idx / GRID_TRACKS;
+
+        Some((track, scene)).filter(|&(track, scene)| track < self.config.tracks && scene < self.config.scenes)
+    }
+
+    /// Takes in what the DAW says about its clips and tracks, returns whether anything changed.
+    pub fn receive(&mut self, msg: &Message) -> bool {
+        let (note, velocity) = match *msg {
+            Message::NoteOn(channel, note, velocity) if channel == midi_channel(self.config.channel) => (note, velocity),
+            Message::NoteOff(channel, note, _) if channel == midi_channel(self.config.channel) => (note, 0),
+            _ => return false,
+        };
+
+        let config = &self.config;
+        let find = |base: U7, count: usize| (0..count).find(|&idx| Self::note(base, idx) == Some(note));
+
+        if let Some(clip) = find(config.clip_note, self.clips.len()) {
+            self.clips[clip] = ClipState::from_velocity(velocity);
+        } else if let Some(track) = find(config.mute_note, config.tracks) {
+            self.muted[track] = velocity > 0;
+        } else if let Some(track) = find(config.solo_note, config.tracks) {
+            self.soloed[track] = velocity > 0;
+        } else {
+            return false;
+        }
+
+        true
+    }
+
+    fn scroll(offset: &mut usize, delta: i32, size: usize, window: usize) {
+        let max = size.saturating_sub(window) as i32;
+        *offset = (*offset as i32 + delta).clamp(0, max) as usize;
+    }
+}
+
+impl PadModeHandler for SessionMode {
+    fn leave(&mut self, _: &mut ModeContext, maschine: &mut dyn Maschine) {
+        for &(btn, _) in &PAD_ACTION_BUTTONS {
+            maschine.set_button_light(btn, 0xFFFFFF, BUTTON_LIGHT_OFF);
+        }
+    }
+
+    fn pad_pressed(&mut self, ctx: &mut ModeContext, _: &mut dyn Maschine, pad_idx: usize, _: f32) {
+        let (track, scene) = match self.pad_clip(pad_idx) {
+            Some(clip) => clip,
+            None => return,
+        };
+
+        let note = match self.pad_action {
+            PadAction::Clip => Self::note(self.config.clip_note, scene * self.config.tracks + track),
+            PadAction::Scene => Self::note(self.config.scene_note, scene),
+            PadAction::Mute => Self::note(self.config.mute_note, track),
+            PadAction::Solo => Self::note(self.config.solo_note, track),
+        };
+
+        if let Some(note) = note {
+            ctx.send_midi_msg(&Message::NoteOn(midi_channel(self.config.channel), note, 127));
+            self.held[pad_idx] = Some(note);
+        }
+    }
+
+    fn pad_released(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize) {
+        if let Some(note) = self.held[pad_idx].take() {
+            ctx.send_midi_msg(&Message::NoteOff(midi_channel(self.config.channel), note, 0));
+        }
+
+        // the pressed pad lit up, it goes back to what the DAW says
+        self.render_lights(ctx, maschine);
+    }
+
+    fn render_lights(&self, ctx: &ModeContext, maschine: &mut dyn Maschine) {
+        let colors = &self.config.colors;
+
+        for pad_idx in 0..16 {
+            let (color, brightness) = match (self.pad_clip(pad_idx), self.pad_action) {
+                (None, _) => (0, 0.0),
+
+                (Some((track, _)), PadAction::Mute) => (ctx.color, if self.muted[track] { 1.0 } else { PAD_RELEASED_BRIGHTNESS }),
+                (Some((track, _)), PadAction::Solo) => (ctx.color, if self.soloed[track] { 1.0 } else { PAD_RELEASED_BRIGHTNESS }),
+
+                (Some((track, scene)), _) => match self.clips[scene * self.config.tracks + track] {
+                    ClipState::Empty => (colors.empty, 1.0),
+                    ClipState::Stopped => (colors.stopped, 1.0),
+                    ClipState::Playing => (colors.playing, 1.0),
+                    ClipState::Recording => (colors.recording, 1.0),
+                },
+            };
+
+            maschine.set_pad_light(pad_idx, color, brightness);
+        }
+
+        for &(btn, action) in &PAD_ACTION_BUTTONS {
+            let brightness = if action == self.pad_action { BUTTON_LIGHT_ON } else { BUTTON_LIGHT_OFF };
+            maschine.set_button_light(btn, 0xFFFFFF, brightness);
+        }
+    }
+
+    fn button_down(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, btn: MaschineButton) -> bool {
+        let tracks = self.config.tracks;
+
+        match btn {
+            MaschineButton::Navleft => Self::scroll(&mut self.track_offset, -1, tracks, GRID_TRACKS),
+            MaschineButton::Navright => Self::scroll(&mut self.track_offset, 1, tracks, GRID_TRACKS),
+
+            _ => match PAD_ACTION_BUTTONS.iter().find(|&&(button, _)| button == btn) {
+                Some(&(_, action)) => {
+                    self.pad_action = if self.pad_action == action { PadAction::Clip } else { action };
+                }
+                None => return false,
+            },
+        }
+
+        self.render_lights(ctx, maschine);
+        true
+    }
+
+    fn encoder_step(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, delta: i32) -> bool {
+        Self::scroll(&mut self.scene_offset, delta, self.config.scenes, GRID_SCENES);
+        self.render_lights(ctx, maschine);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use midi::Channel::{Ch1, Ch2};
+
+    fn session(config: SessionConfig) -> SessionMode {
+        let mut session = SessionMode::new();
+        session.set_config(config);
+        session
+    }
+
+    #[test]
+    fn clip_states() {
+        let mut session = session(SessionConfig::default());
+
+        // track 2 of scene 1
+        assert!(session.receive(&Message::NoteOn(Ch1, 10, 1)));
+        assert_eq!(session.clips[10], ClipState::Stopped);
+        assert!(session.receive(&Message::NoteOn(Ch1, 10, 2)));
+        assert_eq!(session.clips[10], ClipState::Playing);
+        assert!(session.receive(&Message::NoteOn(Ch1, 10, 5)));
+        assert_eq!(session.clips[10], ClipState::Recording);
+        assert!(session.receive(&Message::NoteOff(Ch1, 10, 64)));
+        assert_eq!(session.clips[10], ClipState::Empty);
+    }
+
+    #[test]
+    fn mutes_and_solos() {
+        let mut session = session(SessionConfig::default());
+
+        assert!(session.receive(&Message::NoteOn(Ch1, 73, 127)));
+        assert!(session.receive(&Message::NoteOn(Ch1, 87, 127)));
+        assert_eq!(session.muted, vec![false, true, false, false, false, false, false, false]);
+        assert_eq!(session.soloed, vec![false, false, false, false, false, false, false, true]);
+
+        assert!(session.receive(&Message::NoteOn(Ch1, 73, 0)));
+        assert!(!session.muted[1]);
+    }
+
+    #[test]
+    fn ignores_other_messages() {
+        let mut session = session(SessionConfig::default());
+
+        // scenes only go out, the other channels and CCs aren't the DAW's grid
+        assert!(!session.receive(&Message::NoteOn(Ch1, 64, 1)));
+        assert!(!session.receive(&Message::NoteOn(Ch2, 10, 1)));
+        assert!(!session.receive(&Message::ControlChange(Ch1, 10, 1)));
+        assert!(!session.receive(&Message::NoteOn(Ch1, 100, 1)));
+        assert_eq!(session.clips[10], ClipState::Empty);
+    }
+
+    #[test]
+    fn channel() {
+        let mut session = session(SessionConfig { channel: 2, ..SessionConfig::default() });
+
+        assert!(!session.receive(&Message::NoteOn(Ch1, 0, 1)));
+        assert!(session.receive(&Message::NoteOn(Ch2, 0, 1)));
+    }
+
+    #[test]
+    fn clips_past_note_127_are_left_out() {
+        let mut session = session(SessionConfig {
+            clip_note: 120,
+            scene_note: 0,
+            mute_note: 8,
+            solo_note: 16,
+            ..SessionConfig::default()
+        });
+
+        assert!(session.receive(&Message::NoteOn(Ch1, 127, 2)));
+        assert_eq!(session.clips[7], ClipState::Playing);
+        assert_eq!(SessionMode::note(120, 8), None);
+    }
+
+    #[test]
+    fn pad_window() {
+        let mut session = session(SessionConfig { tracks: 6, scenes: 5, ..SessionConfig::default() });
+
+        // pad 0 is the top left, the first track of the first scene
+        assert_eq!(session.pad_clip(0), Some((0, 0)));
+        assert_eq!(session.pad_clip(6), Some((2, 1)));
+
+        SessionMode::scroll(&mut session.track_offset, 5, 6, GRID_TRACKS);
+        SessionMode::scroll(&mut session.scene_offset, -1, 5, GRID_SCENES);
+        assert_eq!((session.track_offset, session.scene_offset), (2, 0));
+        assert_eq!(session.pad_clip(3), Some((5, 0)));
+
+        SessionMode::scroll(&mut session.scene_offset, 1, 5, GRID_SCENES);
+        assert_eq!(session.pad_clip(15), Some((5, 4)));
+    }
+
+    #[test]
+    fn small_grid() {
+        let session = session(SessionConfig { tracks: 2, scenes: 3, ..SessionConfig::default() });
+
+        assert_eq!(session.pad_clip(1), Some((1, 0)));
+        assert_eq!(session.pad_clip(2), None);
+        assert_eq!(session.pad_clip(12), None);
+    }
+
+    #[test]
+    fn overlap() {
+        assert_eq!(SessionConfig::default().overlap(), None);
+
+        // 16 tracks of 8 scenes run into the scene notes
+        let wide = SessionConfig { tracks: 16, ..SessionConfig::default() };
+        assert_eq!(wide.overlap(), Some(("clip", "scene")));
+
+        let mute_solo = SessionConfig { solo_note: 79, ..SessionConfig::default() };
+        assert_eq!(mute_solo.overlap(), Some(("mute", "solo")));
+
+        // the mapping.toml example
+        let example = SessionConfig {
+            tracks: 16,
+            scenes: 4,
+            mute_note: 68,
+            solo_note: 84,
+            ..SessionConfig::default()
+        };
+        assert_eq!(example.overlap(), None);
+    }
+}
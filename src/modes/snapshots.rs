@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use base::Maschine;
+use knobs::KnobPage;
+use modes::{ModeContext, PadModeHandler};
+use profile::{SnapshotSaver, SNAPSHOTS_PATH};
+use utils::PAD_RELEASED_BRIGHTNESS;
+
+// encoder steps from one snapshot of a morph to the other, two turns
+const MORPH_STEPS: f32 = 32.0;
+
+const STORED_BRIGHTNESS: f32 = 0.3;
+
+/// Where every knob of every page was, by page name so pages can be moved around in
+/// profile.toml without their snapshots ending up on another page.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub pages: BTreeMap<String, Vec<f32>>,
+}
+
+impl Snapshot {
+    fn take(pages: &[KnobPage]) -> Self {
+        Snapshot {
+            pages: pages
+                .iter()
+                .map(|page| (page.name.clone(), page.knobs.iter().map(|knob| knob.position()).collect()))
+                .collect(),
+        }
+    }
+
+    fn position(&self, page: &str, knob_idx: usize) -> Option<f32> {
+        self.pages.get(page).and_then(|page| page.get(knob_idx)).cloned()
+    }
+
+    /// Part of the way to `to`, ending exactly on both snapshots.
+    fn morph_position(&self, to: &Snapshot, page: &str, knob_idx: usize, amount: f32) -> Option<f32> {
+        match (self.position(page, knob_idx), to.position(page, knob_idx)) {
+            (Some(from), Some(to)) => Some(from * (1.0 - amount) + to * amount),
+            _ => None,
+        }
+    }
+}
+
+/// Pads store and recall the knobs of all pages, the encoder morphs between two of them.
+pub struct SnapshotsMode {
+    snapshots: Vec<Option<Snapshot>>,
+    held: Option<usize>,
+    recalled: Option<usize>,
+    morph: Option<(usize, usize)>,
+    morph_amount: f32,
+    // started with the first store
+    saver: Option<SnapshotSaver>,
+}
+
+impl SnapshotsMode {
+    pub fn new() -> Self {
+        SnapshotsMode {
+            snapshots: vec![None; 16],
+            held: None,
+            recalled: None,
+            morph: None,
+            morph_amount: 0.0,
+            saver: None,
+        }
+    }
+
+    pub fn set_snapshots(&mut self, mut snapshots: Vec<Option<Snapshot>>) {
+        snapshots.resize(16, None);
+        self.snapshots = snapshots;
+    }
+
+    /// Puts every knob where `position` says and sends the absolute ones that changed, all of
+    /// them if `resend`. Relative knobs only move, there's nothing they could send.
+    fn move_knobs<F>(ctx: &mut ModeContext, resend: bool, position: F)
+    where
+        F: Fn(&str, usize) -> Option<f32>,
+    {
+        for page in ctx.knob_pages.iter_mut() {
+            for (knob_idx, knob) in page.knobs.iter_mut().enumerate() {
+                let position = match position(&page.name, knob_idx) {
+                    Some(position) => position,
+                    None => continue,
+                };
+
+                let last_msgs = knob.value_msgs();
+                knob.set_position(position);
+                let msgs = knob.value_msgs();
+
+                if msgs.is_empty() || (!resend && msgs == last_msgs) {
+                    continue;
+                }
+
+                for msg in &msgs {
+                    ctx.midi.send(msg);
+                }
+
+                if let Some(parameter) = knob.parameter() {
+                    ctx.parameter_values.insert(parameter, knob.value());
+                }
+            }
+        }
+    }
+
+    fn store(&mut self, ctx: &mut ModeContext, pad_idx: usize) {
+        self.snapshots[pad_idx] = Some(Snapshot::take(ctx.knob_pages));
+        self.recalled = Some(pad_idx);
+        self.morph = None;
+
+        self.saver
+            .get_or_insert_with(|| SnapshotSaver::new(PathBuf::from(SNAPSHOTS_PATH)))
+            .save(&self.snapshots);
+    }
+
+    fn recall(&mut self, ctx: &mut ModeContext, pad_idx: usize) {
+        let snapshot = match self.snapshots[pad_idx] {
+            Some(ref snapshot) => snapshot,
+            None => return,
+        };
+
+        SnapshotsMode::move_knobs(ctx, true, |page, knob_idx| snapshot.position(page, knob_idx));
+
+        self.recalled = Some(pad_idx);
+        self.morph = None;
+    }
+}
+
+impl PadModeHandler for SnapshotsMode {
+    fn leave(&mut self, _: &mut ModeContext, _: &mut dyn Maschine) {
+        self.held = None;
+    }
+
+    fn pad_pressed(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, pad_idx: usize, _: f32) {
+        match self.held {
+            Some(held) if held != pad_idx => {
+                if self.snapshots[held].is_some() && self.snapshots[pad_idx].is_some() {
+                    self.morph = Some((held, pad_idx));
+                    self.morph_amount = 0.0;
+                }
+            }
+
+            _ => {
+                if maschine.get_mod() == 1 {
+                    self.store(ctx, pad_idx);
+                } else {
+                    self.recall(ctx, pad_idx);
+                }
+
+                self.held = Some(pad_idx);
+            }
+        }
+
+        self.render_lights(ctx, maschine);
+    }
+
+    fn pad_released(&mut self, _: &mut ModeContext, _: &mut dyn Maschine, pad_idx: usize) {
+        if self.held == Some(pad_idx) {
+            self.held = None;
+        }
+    }
+
+    fn render_lights(&self, ctx: &ModeContext, maschine: &mut dyn Maschine) {
+        for (pad_idx, snapshot) in self.snapshots.iter().enumerate() {
+            let brightness = match self.morph {
+                Some((from, _)) if from == pad_idx => (1.0 - self.morph_amount).max(STORED_BRIGHTNESS),
+                Some((_, to)) if to == pad_idx => self.morph_amount.max(STORED_BRIGHTNESS),
+                None if self.recalled == Some(pad_idx) => 1.0,
+                _ if snapshot.is_some() => STORED_BRIGHTNESS,
+                _ => PAD_RELEASED_BRIGHTNESS,
+            };
+
+            maschine.set_pad_light(pad_idx, ctx.color, brightness);
+        }
+    }
+
+    fn encoder_step(&mut self, ctx: &mut ModeContext, maschine: &mut dyn Maschine, delta: i32) -> bool {
+        let (from, to) = match self.morph {
+            Some(morph) => morph,
+            None => return false,
+        };
+
+        self.morph_amount = (self.morph_amount + delta as f32 / MORPH_STEPS).clamp(0.0, 1.0);
+
+        if let (Some(from), Some(to)) = (&self.snapshots[from], &self.snapshots[to]) {
+            let amount = self.morph_amount;
+
+            SnapshotsMode::move_knobs(ctx, false, |page, knob_idx| from.morph_position(to, page, knob_idx, amount));
+        }
+
+        self.render_lights(ctx, maschine);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(knobs: Vec<f32>) -> Snapshot {
+        Snapshot {
+            pages: vec![(String::new(), knobs)].into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn morph_ends_on_the_snapshots() {
+        let from = snapshot(vec![0.1, 0.3, 0.0]);
+        let to = snapshot(vec![0.7, 0.9, 1.0]);
+
+        for knob_idx in 0..3 {
+            assert_eq!(from.morph_position(&to, "", knob_idx, 0.0), from.position("", knob_idx));
+            assert_eq!(from.morph_position(&to, "", knob_idx, 1.0), to.position("", knob_idx));
+        }
+
+        assert_eq!(from.morph_position(&to, "", 2, 0.5), Some(0.5));
+    }
+
+    #[test]
+    fn morph_steps_reach_the_target() {
+        let from = snapshot(vec![0.1]);
+        let to = snapshot(vec![0.7]);
+
+        let mut amount: f32 = 0.0;
+        for _ in 0..MORPH_STEPS as usize {
+            amount = (amount + 1.0 / MORPH_STEPS).clamp(0.0, 1.0);
+        }
+
+        assert_eq!(from.morph_position(&to, "", 0, amount), Some(0.7));
+    }
+
+    #[test]
+    fn morph_skips_missing_knobs() {
+        let from = snapshot(vec![0.1, 0.2]);
+        let to = snapshot(vec![0.7]);
+
+        assert_eq!(from.morph_position(&to, "", 1, 0.5), None);
+        assert_eq!(from.morph_position(&to, "Synth", 0, 0.5), None);
+    }
+}
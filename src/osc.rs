@@ -136,149 +136,5 @@ pub fn btn_to_osc_button_map(btn: MaschineButton) -> &'static str {
         MaschineButton::R6 => "R6",
         MaschineButton::R7 => "R7",
         MaschineButton::R8 => "R8",
-
-        MaschineButton::A1 => "A1",
-        MaschineButton::A2 => "A2",
-        MaschineButton::A3 => "A3",
-        MaschineButton::A4 => "A4",
-        MaschineButton::A5 => "A5",
-        MaschineButton::A6 => "A6",
-        MaschineButton::A7 => "A7",
-        MaschineButton::A8 => "A8",
-
-        MaschineButton::B1 => "B1",
-        MaschineButton::B2 => "B2",
-        MaschineButton::B3 => "B3",
-        MaschineButton::B4 => "B4",
-        MaschineButton::B5 => "B5",
-        MaschineButton::B6 => "B6",
-        MaschineButton::B7 => "B7",
-        MaschineButton::B8 => "B8",
-
-        MaschineButton::C1 => "C1",
-        MaschineButton::C2 => "C2",
-        MaschineButton::C3 => "C3",
-        MaschineButton::C4 => "C4",
-        MaschineButton::C5 => "C5",
-        MaschineButton::C6 => "C6",
-        MaschineButton::C7 => "C7",
-        MaschineButton::C8 => "C8",
-
-        MaschineButton::D1 => "D1",
-        MaschineButton::D2 => "D2",
-        MaschineButton::D3 => "D3",
-        MaschineButton::D4 => "D4",
-        MaschineButton::D5 => "D5",
-        MaschineButton::D6 => "D6",
-        MaschineButton::D7 => "D7",
-        MaschineButton::D8 => "D8",
-
-        MaschineButton::E1 => "E1",
-        MaschineButton::E2 => "E2",
-        MaschineButton::E3 => "E3",
-        MaschineButton::E4 => "E4",
-        MaschineButton::E5 => "E5",
-        MaschineButton::E6 => "E6",
-        MaschineButton::E7 => "E7",
-        MaschineButton::E8 => "E8",
-
-        MaschineButton::FF1 => "FF1",
-        MaschineButton::FF2 => "FF2",
-        MaschineButton::FF3 => "FF3",
-        MaschineButton::FF4 => "FF4",
-        MaschineButton::FF5 => "FF5",
-        MaschineButton::FF6 => "FF6",
-        MaschineButton::FF7 => "FF8",
-        MaschineButton::FF8 => "FF8",
-
-        MaschineButton::G1 => "G1",
-        MaschineButton::G2 => "G2",
-        MaschineButton::G3 => "G3",
-        MaschineButton::G4 => "G4",
-        MaschineButton::G5 => "G5",
-        MaschineButton::G6 => "G6",
-        MaschineButton::G7 => "G7",
-        MaschineButton::G8 => "G8",
-
-        MaschineButton::H1 => "H1",
-        MaschineButton::H2 => "H2",
-        MaschineButton::H3 => "H3",
-        MaschineButton::H4 => "H4",
-        MaschineButton::H5 => "H5",
-        MaschineButton::H6 => "H6",
-        MaschineButton::H7 => "H7",
-        MaschineButton::H8 => "H8",
-
-        MaschineButton::I1 => "I1",
-        MaschineButton::I2 => "I2",
-        MaschineButton::I3 => "I3",
-        MaschineButton::I4 => "I4",
-        MaschineButton::I5 => "I5",
-        MaschineButton::I6 => "I6",
-        MaschineButton::I7 => "I7",
-        MaschineButton::I8 => "I8",
-
-        MaschineButton::J1 => "J1",
-        MaschineButton::J2 => "J2",
-        MaschineButton::J3 => "J3",
-        MaschineButton::J4 => "J4",
-        MaschineButton::J5 => "J5",
-        MaschineButton::J6 => "J6",
-        MaschineButton::J7 => "J7",
-        MaschineButton::J8 => "J8",
-
-        MaschineButton::K1 => "K1",
-        MaschineButton::K2 => "K2",
-        MaschineButton::K3 => "K3",
-        MaschineButton::K4 => "K4",
-        MaschineButton::K5 => "K5",
-        MaschineButton::K6 => "K6",
-        MaschineButton::K7 => "K7",
-        MaschineButton::K8 => "K8",
-
-        MaschineButton::L1 => "L1",
-        MaschineButton::L2 => "L2",
-        MaschineButton::L3 => "L3",
-        MaschineButton::L4 => "L4",
-        MaschineButton::L5 => "L5",
-        MaschineButton::L6 => "L6",
-        MaschineButton::L7 => "L7",
-        MaschineButton::L8 => "L8",
-
-        MaschineButton::M1 => "M1",
-        MaschineButton::M2 => "M2",
-        MaschineButton::M3 => "M3",
-        MaschineButton::M4 => "M4",
-        MaschineButton::M5 => "M5",
-        MaschineButton::M6 => "M6",
-        MaschineButton::M7 => "M7",
-        MaschineButton::M8 => "M8",
-
-        MaschineButton::N1 => "N1",
-        MaschineButton::N2 => "N2",
-        MaschineButton::N3 => "N3",
-        MaschineButton::N4 => "N4",
-        MaschineButton::N5 => "N5",
-        MaschineButton::N6 => "N6",
-        MaschineButton::N7 => "N7",
-        MaschineButton::N8 => "N8",
-
-        MaschineButton::O1 => "O1",
-        MaschineButton::O2 => "O2",
-        MaschineButton::O3 => "O3",
-        MaschineButton::O4 => "O4",
-        MaschineButton::O5 => "O5",
-        MaschineButton::O6 => "O6",
-        MaschineButton::O7 => "O7",
-        MaschineButton::O8 => "O8",
-
-        MaschineButton::P1 => "P1",
-        MaschineButton::P2 => "P2",
-        MaschineButton::P3 => "P3",
-        MaschineButton::P4 => "P4",
-        MaschineButton::P5 => "P5",
-        MaschineButton::P6 => "P6",
-        MaschineButton::P7 => "P7",
-        MaschineButton::P8 => "P8",
     }
 }
\ No newline at end of file
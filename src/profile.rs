@@ -0,0 +1,346 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use midi::U7;
+
+use base::KNOB_STEPS_PER_TURN;
+use knobs::{EncoderPush, Knob, KnobEncoding, KnobPage, Takeover};
+use modes::Snapshot;
+use utils::{check_velocity_zones, midi_channel, VelocityZone, KNOB_CC_BASE};
+
+/// Read from the working directory at startup, like picturetest.png.
+pub const PROFILE_PATH: &str = "profile.toml";
+
+/// Written by the snapshot pad mode, so profile.toml and its comments stay untouched.
+pub const SNAPSHOTS_PATH: &str = "snapshots.toml";
+
+/// Everything about the setup that isn't hardware, see profile.toml for a commented example.
+#[derive(Deserialize)]
+pub struct Profile {
+    /// The row of pads that bends the pitch, counted from the top.
+    #[serde(default)]
+    pub pitch_strip_row: Option<usize>,
+
+    #[serde(default = "default_encoder_push")]
+    pub encoder_push: EncoderPush,
+
+    /// Adds a Mackie Control port and makes the MK2 an MCU on it.
+    #[serde(default)]
+    pub mackie_control: bool,
+
+    #[serde(default = "default_knob_pages")]
+    pub knob_pages: Vec<KnobPageConfig>,
+
+    #[serde(default)]
+    pub velocity_zones: Vec<VelocityZonesConfig>,
+
+    #[serde(skip)]
+    pub snapshots: Vec<Option<Snapshot>>,
+}
+
+#[derive(Deserialize)]
+pub struct KnobPageConfig {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub knobs: Vec<KnobConfig>,
+}
+
+#[derive(Deserialize)]
+pub struct KnobConfig {
+    #[serde(default)]
+    pub name: String,
+    pub cc: U7,
+    /// 1-16, like on the DAW side.
+    #[serde(default = "default_channel")]
+    pub channel: u8,
+    #[serde(default)]
+    pub min: U7,
+    #[serde(default = "default_max")]
+    pub max: U7,
+    #[serde(default = "default_encoding")]
+    pub encoding: KnobEncoding,
+    #[serde(default = "default_takeover")]
+    pub takeover: Takeover,
+}
+
+/// The velocity zones of one pad in notes mode.
+#[derive(Deserialize)]
+pub struct VelocityZonesConfig {
+    pub pad: usize,
+    pub zones: Vec<VelocityZoneConfig>,
+}
+
+#[derive(Deserialize)]
+pub struct VelocityZoneConfig {
+    #[serde(default)]
+    pub min_velocity: U7,
+    /// The pad's usual note when left out.
+    #[serde(default)]
+    pub note: Option<U7>,
+    #[serde(default = "default_channel")]
+    pub channel: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotsFile {
+    #[serde(default)]
+    snapshot: Vec<SnapshotConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotConfig {
+    pad: usize,
+    #[serde(flatten)]
+    snapshot: Snapshot,
+}
+
+impl KnobConfig {
+    /// A knob whose encoding can't go out on its CC is an error.
+    pub fn check(&self, path: &Path) {
+        if !self.encoding.fits(self.cc) {
+            panic!("{} needs a CC below 32 for knob {} in {}", self.encoding.name(), self.cc, path.display());
+        }
+    }
+}
+
+fn default_knob_pages() -> Vec<KnobPageConfig> {
+    vec![KnobPageConfig {
+        name: String::new(),
+        knobs: vec![],
+    }]
+}
+
+fn default_encoder_push() -> EncoderPush {
+    EncoderPush::Cc
+}
+
+fn default_channel() -> u8 {
+    1
+}
+
+fn default_max() -> U7 {
+    127
+}
+
+fn default_encoding() -> KnobEncoding {
+    KnobEncoding::Absolute
+}
+
+fn default_takeover() -> Takeover {
+    Takeover::Off
+}
+
+impl Profile {
+    /// A missing profile isn't an error, everything just keeps its default. A broken one is.
+    pub fn load(path: &Path) -> Profile {
+        let profile = match fs::read_to_string(path) {
+            Ok(profile) => profile,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => {
+                println!(" :: no {}, using the defaults", path.display());
+                String::new()
+            }
+            Err(err) => panic!("couldn't read {}: {}", path.display(), err),
+        };
+
+        let mut profile: Profile = match toml::from_str(&profile) {
+            Ok(profile) => profile,
+            Err(err) => panic!("couldn't parse {}: {}", path.display(), err),
+        };
+
+        if profile.knob_pages.is_empty() {
+            profile.knob_pages = default_knob_pages();
+        }
+
+        // snapshots find their pages by name
+        for (page_idx, page) in profile.knob_pages.iter().enumerate() {
+            if profile.knob_pages[..page_idx].iter().any(|other| other.name == page.name) {
+                panic!("two knob pages are called {:?} in {}", page.name, path.display());
+            }
+        }
+
+        for knob in profile.knob_pages.iter().flat_map(|page| &page.knobs) {
+            knob.check(path);
+        }
+
+        for (pad_idx, zones) in profile.velocity_zones() {
+            if pad_idx > 15 {
+                panic!("unknown pad {} in {}", pad_idx, path.display());
+            }
+
+            if let Err(err) = check_velocity_zones(&zones) {
+                panic!("{} for pad {} in {}", err, pad_idx, path.display());
+            }
+        }
+
+        profile.snapshots = load_snapshots(Path::new(SNAPSHOTS_PATH));
+
+        profile
+    }
+
+    pub fn knob_pages(&self) -> Vec<KnobPage> {
+        self.knob_pages.iter().map(KnobPage::from).collect()
+    }
+
+    pub fn velocity_zones(&self) -> Vec<(usize, Vec<VelocityZone>)> {
+        self.velocity_zones
+            .iter()
+            .map(|config| {
+                let zones = config
+                    .zones
+                    .iter()
+                    .map(|zone| VelocityZone {
+                        min_velocity: zone.min_velocity,
+                        note: zone.note,
+                        channel: midi_channel(zone.channel),
+                    })
+                    .collect();
+
+                (config.pad, zones)
+            })
+            .collect()
+    }
+}
+
+/// Knobs left out of a page send the default CCs.
+impl<'a> From<&'a KnobPageConfig> for KnobPage {
+    fn from(config: &'a KnobPageConfig) -> KnobPage {
+        let knobs = (0..8)
+            .map(|knob_idx| {
+                let mut knob = Knob::new(KNOB_CC_BASE + knob_idx as U7, KNOB_STEPS_PER_TURN);
+
+                if let Some(knob_config) = config.knobs.get(knob_idx) {
+                    knob.name = knob_config.name.clone();
+                    knob.channel = midi_channel(knob_config.channel);
+                    knob.cc = knob_config.cc;
+                    knob.min = knob_config.min;
+                    knob.max = knob_config.max;
+                    knob.encoding = knob_config.encoding;
+                    knob.takeover = knob_config.takeover;
+
+                    if knob.encoding == KnobEncoding::PitchBend {
+                        knob.set_default_position(0.5);
+                    }
+                }
+
+                knob
+            })
+            .collect();
+
+        KnobPage {
+            name: config.name.clone(),
+            knobs,
+        }
+    }
+}
+
+/// One per pad, nothing stored yet is fine. Snapshots on pads that don't exist are dropped.
+fn load_snapshots(path: &Path) -> Vec<Option<Snapshot>> {
+    let mut snapshots = vec![None; 16];
+
+    let file = match fs::read_to_string(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == ErrorKind::NotFound => return snapshots,
+        Err(err) => panic!("couldn't read {}: {}", path.display(), err),
+    };
+
+    let file: SnapshotsFile = match toml::from_str(&file) {
+        Ok(file) => file,
+        Err(err) => panic!("couldn't parse {}: {}", path.display(), err),
+    };
+
+    for config in file.snapshot {
+        if let Some(snapshot) = snapshots.get_mut(config.pad) {
+            *snapshot = Some(config.snapshot);
+        }
+    }
+
+    snapshots
+}
+
+/// Writes the snapshots on a thread of its own, so storing one doesn't hold up the pads and
+/// knobs. Only the latest of several waiting saves is written.
+pub struct SnapshotSaver {
+    sender: Sender<Vec<Option<Snapshot>>>,
+}
+
+impl SnapshotSaver {
+    pub fn new(path: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::channel::<Vec<Option<Snapshot>>>();
+
+        thread::spawn(move || {
+            while let Ok(snapshots) = receiver.recv() {
+                let snapshots = receiver.try_iter().last().unwrap_or(snapshots);
+                save_snapshots(&path, &snapshots);
+            }
+        });
+
+        SnapshotSaver { sender }
+    }
+
+    pub fn save(&self, snapshots: &[Option<Snapshot>]) {
+        // the thread only stops with us
+        let _ = self.sender.send(snapshots.to_vec());
+    }
+}
+
+/// Saving happens while playing, so a failure is only reported.
+fn save_snapshots(path: &Path, snapshots: &[Option<Snapshot>]) {
+    let file = SnapshotsFile {
+        snapshot: snapshots
+            .iter()
+            .enumerate()
+            .filter_map(|(pad, snapshot)| {
+                snapshot.as_ref().map(|snapshot| SnapshotConfig {
+                    pad,
+                    snapshot: snapshot.clone(),
+                })
+            })
+            .collect(),
+    };
+
+    let result = toml::to_string(&file)
+        .map_err(|err| err.to_string())
+        .and_then(|file| fs::write(path, file).map_err(|err| err.to_string()));
+
+    if let Err(err) = result {
+        println!(" :: couldn't save {}: {}", path.display(), err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::process;
+
+    fn snapshot(positions: Vec<(&str, Vec<f32>)>) -> Snapshot {
+        Snapshot {
+            pages: positions.into_iter().map(|(name, knobs)| (name.to_string(), knobs)).collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let path = std::env::temp_dir().join(format!("maschine-snapshots-{}.toml", process::id()));
+
+        let mut snapshots = vec![None; 16];
+        snapshots[0] = Some(snapshot(vec![("", vec![0.0, 0.25, 1.0])]));
+        snapshots[15] = Some(snapshot(vec![("", vec![0.5]), ("Synth", vec![0.1, 0.7])]));
+
+        save_snapshots(&path, &snapshots);
+        let loaded = load_snapshots(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, snapshots);
+    }
+
+    #[test]
+    fn no_snapshots_file() {
+        let path = std::env::temp_dir().join("maschine-snapshots-missing.toml");
+        assert_eq!(load_snapshots(&path), vec![None; 16]);
+    }
+}
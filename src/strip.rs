@@ -0,0 +1,156 @@
+use midi::{Channel, Message, U14};
+use midi::Channel::Ch1;
+
+const PITCH_BEND_CENTER: U14 = 8192;
+
+/// How far either side of the middle of the row still plays in tune, out of the whole row.
+const CENTER_DEAD_ZONE: f32 = 0.05;
+
+/// A row of 4 pads that bends the pitch, in whichever pad mode is active. Where on the row it's
+/// pressed, weighted by how hard each pad is pressed, sets which way it bends, and how hard it's
+/// pressed all together how far, so a light touch only bends a little. Letting go of the row
+/// springs back to the center.
+pub struct PitchStrip {
+    pub channel: Channel,
+
+    // rows count from the top, like the pads
+    row: Option<usize>,
+    pressures: [f32; 4],
+    bend: U14,
+}
+
+impl PitchStrip {
+    pub fn new() -> Self {
+        PitchStrip {
+            channel: Ch1,
+
+            row: None,
+            pressures: [0.0; 4],
+            bend: PITCH_BEND_CENTER,
+        }
+    }
+
+    pub fn row(&self) -> Option<usize> {
+        self.row
+    }
+
+    /// Moves the strip, or turns it off with `None`. Returns the Pitch Bend that recenters it
+    /// if the old row was still bending.
+    pub fn set_row(&mut self, row: Option<usize>) -> Option<Message> {
+        self.row = row.filter(|&row| row < 4);
+        self.pressures = [0.0; 4];
+        self.update_bend()
+    }
+
+    /// Which column of the strip a pad is, if it's on the strip.
+    pub fn column(&self, pad_idx: usize) -> Option<usize> {
+        match self.row {
+            Some(row) if pad_idx / 4 == row => Some(pad_idx % 4),
+            _ => None,
+        }
+    }
+
+    /// Returns the new Pitch Bend, nothing if the pressure didn't change it. A pressure of 0 is
+    /// a released pad.
+    pub fn set_pressure(&mut self, column: usize, pressure: f32) -> Option<Message> {
+        self.pressures[column] = pressure;
+        self.update_bend()
+    }
+
+    fn update_bend(&mut self) -> Option<Message> {
+        let bend = strip_bend(&self.pressures);
+        if bend == self.bend {
+            return None;
+        }
+
+        self.bend = bend;
+        Some(Message::PitchBend(self.channel, bend))
+    }
+}
+
+/// The bend for the pressures of the 4 pads: the weighted position on the row from -1 (left) to
+/// 1 (right), outside the dead zone, times the total pressure up to 1.
+fn strip_bend(pressures: &[f32; 4]) -> U14 {
+    let total: f32 = pressures.iter().sum();
+    if total <= 0.0 {
+        return PITCH_BEND_CENTER;
+    }
+
+    let weighted: f32 = pressures.iter().enumerate().map(|(column, &pressure)| column as f32 * pressure).sum();
+    let position = weighted / total / 1.5 - 1.0;
+
+    let dead_zone = CENTER_DEAD_ZONE * 2.0;
+    if position.abs() <= dead_zone {
+        return PITCH_BEND_CENTER;
+    }
+
+    let amount = position.signum() * (position.abs() - dead_zone) / (1.0 - dead_zone) * total.min(1.0);
+    (((amount + 1.0) / 2.0) * 16383.0).round() as U14
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bend(msg: Option<Message>) -> Option<U14> {
+        match msg {
+            Some(Message::PitchBend(Ch1, bend)) => Some(bend),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn ends_of_the_row() {
+        assert_eq!(strip_bend(&[1.0, 0.0, 0.0, 0.0]), 0);
+        assert_eq!(strip_bend(&[0.0, 0.0, 0.0, 1.0]), 16383);
+    }
+
+    #[test]
+    fn weighted_position() {
+        assert_eq!(strip_bend(&[0.0, 0.5, 0.5, 0.0]), PITCH_BEND_CENTER);
+        assert_eq!(strip_bend(&[0.5, 0.0, 0.0, 0.5]), PITCH_BEND_CENTER);
+
+        let left = strip_bend(&[0.5, 0.5, 0.0, 0.0]);
+        assert!(left < PITCH_BEND_CENTER);
+        assert!(strip_bend(&[0.8, 0.2, 0.0, 0.0]) < left);
+        assert!(strip_bend(&[0.0, 0.0, 0.5, 0.5]) > PITCH_BEND_CENTER);
+    }
+
+    #[test]
+    fn light_touch_bends_a_little() {
+        let light = strip_bend(&[0.1, 0.0, 0.0, 0.0]);
+        assert!(light < PITCH_BEND_CENTER);
+        assert!(light > 7000);
+
+        assert!(strip_bend(&[0.5, 0.0, 0.0, 0.0]) < light);
+    }
+
+    #[test]
+    fn center_dead_zone() {
+        assert_eq!(strip_bend(&[0.0, 0.52, 0.48, 0.0]), PITCH_BEND_CENTER);
+        assert_eq!(strip_bend(&[0.0, 0.48, 0.52, 0.0]), PITCH_BEND_CENTER);
+    }
+
+    #[test]
+    fn springs_back() {
+        let mut strip = PitchStrip::new();
+        strip.set_row(Some(3));
+
+        assert_eq!(strip.column(13), Some(1));
+        assert_eq!(strip.column(1), None);
+
+        assert_eq!(bend(strip.set_pressure(0, 1.0)), Some(0));
+        assert_eq!(strip.set_pressure(0, 1.0), None);
+        assert_eq!(bend(strip.set_pressure(0, 0.0)), Some(PITCH_BEND_CENTER));
+    }
+
+    #[test]
+    fn moving_the_row_recenters() {
+        let mut strip = PitchStrip::new();
+        strip.set_row(Some(3));
+        strip.set_pressure(3, 1.0);
+
+        assert_eq!(bend(strip.set_row(None)), Some(PITCH_BEND_CENTER));
+        assert_eq!(strip.column(15), None);
+    }
+}
@@ -1,16 +1,201 @@
+use midi::constants::{CC_DATA_ENTRY_MSB, CC_RPN_LSB, CC_RPN_MSB};
 use midi::*;
+use std::time::Duration;
+
+use base::MaschineButton;
 
 pub const PAD_RELEASED_BRIGHTNESS: f32 = 0.015;
 
 #[allow(dead_code)]
+#[derive(Copy, Clone)]
 pub enum PressureShape {
     Linear,
     Exponential(f32),
     Constant(f32),
 }
 
+impl PressureShape {
+    pub fn velocity(&self, pressure: f32) -> U7 {
+        (match *self {
+            PressureShape::Linear => pressure,
+            PressureShape::Exponential(power) => pressure.powf(power),
+            PressureShape::Constant(c_pressure) => c_pressure,
+        } * 127.0) as U7
+    }
+}
+
 pub const PAD_NOTE_MAP: [U7; 16] = [12, 13, 14, 15, 8, 9, 10, 11, 4, 5, 6, 7, 0, 1, 2, 3];
 
+// CC 102-117 are undefined in the MIDI spec, so they make a safe default for the pad faders.
+pub const PAD_CC_BASE: U7 = 102;
+
+#[derive(Copy, Clone, Debug)]
+pub enum PadControl {
+    Cc(U7),
+    Nrpn(U14),
+}
+
+/// What a pad fader does when the pad is let go.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PadRelease {
+    /// Latch the peak pressure of the press, so the value doesn't fall off with the finger.
+    Hold,
+    /// Follow the pressure and send 0 on release.
+    SnapBack,
+}
+
+/// Which Bank Select controllers go out ahead of a program change.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BankSelect {
+    Off,
+    Msb,
+    Lsb,
+    MsbLsb,
+}
+
+pub fn bank_select_msgs(channel: Channel, mode: BankSelect, bank: U14) -> Vec<Message> {
+    let msb = Message::ControlChange(channel, 0, ((bank >> 7) & 0x7F) as U7);
+    let lsb = Message::ControlChange(channel, 32, (bank & 0x7F) as U7);
+
+    match mode {
+        BankSelect::Off => vec![],
+        BankSelect::Msb => vec![Message::ControlChange(channel, 0, (bank & 0x7F) as U7)],
+        BankSelect::Lsb => vec![lsb],
+        BankSelect::MsbLsb => vec![msb, lsb],
+    }
+}
+
+// MPE lower zone: channel 1 is the manager, every other channel is a member channel.
+pub const MPE_MANAGER_CHANNEL: Channel = Ch1;
+pub const MPE_MEMBER_CHANNELS: [Channel; 15] = [
+    Ch2, Ch3, Ch4, Ch5, Ch6, Ch7, Ch8, Ch9, Ch10, Ch11, Ch12, Ch13, Ch14, Ch15, Ch16,
+];
+pub const MPE_SLIDE_CC: U7 = 74;
+
+/// MPE Configuration Message (RPN 6), sets up the zone with `member_channels` channels. Zero
+/// member channels turns MPE off on the receiving end.
+pub fn mpe_configuration_msgs(member_channels: U7) -> Vec<Message> {
+    vec![
+        Message::ControlChange(MPE_MANAGER_CHANNEL, CC_RPN_MSB, 0),
+        Message::ControlChange(MPE_MANAGER_CHANNEL, CC_RPN_LSB, 6),
+        Message::ControlChange(MPE_MANAGER_CHANNEL, CC_DATA_ENTRY_MSB, member_channels),
+        // RPN null, so stray data entry doesn't land on RPN 6
+        Message::ControlChange(MPE_MANAGER_CHANNEL, CC_RPN_MSB, 127),
+        Message::ControlChange(MPE_MANAGER_CHANNEL, CC_RPN_LSB, 127),
+    ]
+}
+
+// the encoder sends CC 15 and its push CC 14, the knobs CC 16-23
+pub const ENCODER_PUSH_CC: U7 = 14;
+pub const ENCODER_CC: U7 = 15;
+pub const KNOB_CC_BASE: U7 = 16;
+
+// what the knobs send while Volume, Swing or Tempo is lit, 85 and 86 are undefined CCs
+pub const VOLUME_CC: U7 = 7;
+pub const SWING_CC: U7 = 85;
+pub const TEMPO_CC: U7 = 86;
+
+pub const TEMPO_MIN_BPM: f32 = 40.0;
+pub const TEMPO_MAX_BPM: f32 = 240.0;
+pub const DEFAULT_BPM: f32 = 75.0;
+
+/// The internal sequencer plays 16th notes, a note on and a note off each `get_seq_speed` ms
+/// apart.
+pub fn bpm_to_seq_speed(bpm: f32) -> usize {
+    (60000.0 / 4.0 / 2.0 / bpm).round() as usize
+}
+
+// Shift+Tempo taps the tempo, averaged over this many taps
+pub const TAP_TEMPO_TAPS: usize = 4;
+pub const TAP_TEMPO_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub const BUTTON_LIGHT_ON: f32 = 127.0;
+pub const BUTTON_LIGHT_OFF: f32 = 0.0;
+
+pub const GROUP_BUTTONS: [MaschineButton; 8] = [
+    MaschineButton::GroupA,
+    MaschineButton::GroupB,
+    MaschineButton::GroupC,
+    MaschineButton::GroupD,
+    MaschineButton::GroupE,
+    MaschineButton::GroupF,
+    MaschineButton::GroupG,
+    MaschineButton::GroupH,
+];
+
+pub const GROUP_NAMES: [&str; 8] = ["A", "B", "C", "D", "E", "F", "G", "H"];
+
+pub const MIDI_CHANNELS: [Channel; 16] = [
+    Ch1, Ch2, Ch3, Ch4, Ch5, Ch6, Ch7, Ch8, Ch9, Ch10, Ch11, Ch12, Ch13, Ch14, Ch15, Ch16,
+];
+
+/// Channels as people count them, 1-16.
+pub fn midi_channel(number: u8) -> Channel {
+    MIDI_CHANNELS[(number.clamp(1, 16) - 1) as usize]
+}
+
+pub const MAX_VELOCITY_ZONES: usize = 4;
+
+/// A pad layer, played when a pad is struck at `min_velocity` or harder. Without a note the
+/// pad plays its usual note, just on the zone's channel.
+#[derive(Copy, Clone)]
+pub struct VelocityZone {
+    pub min_velocity: U7,
+    pub note: Option<U7>,
+    pub channel: Channel,
+}
+
+/// A pad can have up to `MAX_VELOCITY_ZONES` zones in any order, but no two can start at the
+/// same velocity, one of them would never play.
+pub fn check_velocity_zones(zones: &[VelocityZone]) -> Result<(), String> {
+    if zones.len() > MAX_VELOCITY_ZONES {
+        return Err(format!("{} velocity zones, at most {} fit", zones.len(), MAX_VELOCITY_ZONES));
+    }
+
+    for (idx, zone) in zones.iter().enumerate() {
+        if zones[..idx].iter().any(|other| other.min_velocity == zone.min_velocity) {
+            return Err(format!("two velocity zones start at {}", zone.min_velocity));
+        }
+    }
+
+    Ok(())
+}
+
 pub fn usage(prog_name: &String) {
     println!("usage: {} <hidraw device>", prog_name);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bank_select() {
+        assert_eq!(bank_select_msgs(Ch1, BankSelect::Off, 5), vec![]);
+        assert_eq!(bank_select_msgs(Ch1, BankSelect::Msb, 5), vec![Message::ControlChange(Ch1, 0, 5)]);
+        assert_eq!(bank_select_msgs(Ch2, BankSelect::Lsb, 5), vec![Message::ControlChange(Ch2, 32, 5)]);
+
+        // MSB first, then LSB
+        assert_eq!(
+            bank_select_msgs(Ch1, BankSelect::MsbLsb, 0x3FFF),
+            vec![Message::ControlChange(Ch1, 0, 127), Message::ControlChange(Ch1, 32, 127)]
+        );
+        assert_eq!(
+            bank_select_msgs(Ch1, BankSelect::MsbLsb, 200),
+            vec![Message::ControlChange(Ch1, 0, 1), Message::ControlChange(Ch1, 32, 72)]
+        );
+    }
+
+    fn zone(min_velocity: U7) -> VelocityZone {
+        VelocityZone { min_velocity, note: None, channel: Ch1 }
+    }
+
+    #[test]
+    fn velocity_zones() {
+        assert!(check_velocity_zones(&[]).is_ok());
+        assert!(check_velocity_zones(&[zone(100), zone(0), zone(64), zone(1)]).is_ok());
+
+        assert!(check_velocity_zones(&[zone(0), zone(32), zone(64), zone(96), zone(127)]).is_err());
+        assert!(check_velocity_zones(&[zone(0), zone(64), zone(64)]).is_err());
+    }
+}